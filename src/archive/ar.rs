@@ -0,0 +1,164 @@
+//! Contains ar-specific building and unpacking functions
+//!
+//! ar is the format of Debian packages and static libraries, its archives only hold files, without directories.
+
+use std::{
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fs_err as fs;
+
+use crate::{
+    archive::{self, filter::MemberFilter, rewrite::PathRewrite},
+    error::FinalError,
+    info,
+    integrity::TestPosition,
+    list::FileInArchive,
+    utils::{strip_cur_dir, to_utf, Bytes},
+};
+
+/// Unpacks the archive given by `archive` into the folder given by `into`.
+/// Assumes that output_folder is empty
+pub fn unpack_archive(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    mut display_handle: impl Write,
+) -> crate::Result<Vec<PathBuf>> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+    let mut archive = ar::Archive::new(reader);
+
+    let mut files_unpacked = vec![];
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let header = entry.header();
+        let name = enclosed_name(header.identifier()).filter(|name| filter.is_match(name));
+        let file_path = match name.and_then(|name| rewrite.apply(&name)) {
+            Some(name) => output_folder.join(name),
+            None => continue,
+        };
+        let (size, mode, mtime) = (header.size(), header.mode(), header.mtime());
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        info!(@display_handle, inaccessible, "{:?} extracted. ({})", strip_cur_dir(&file_path), Bytes::new(size));
+
+        let mut output_file = fs::File::create(&file_path)?;
+        io::copy(&mut entry, &mut output_file)?;
+        output_file.file().set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+
+        // Some tools write no mode at all, the default permissions are kept then
+        #[cfg(unix)]
+        if mode & 0o7777 != 0 {
+            use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+            fs::set_permissions(&file_path, Permissions::from_mode(mode & 0o7777))?;
+        }
+
+        files_unpacked.push(file_path);
+    }
+
+    Ok(files_unpacked)
+}
+
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+pub fn cat_member(reader: Box<dyn Read>, member: &Path, mut writer: impl Write) -> crate::Result<bool> {
+    let mut archive = ar::Archive::new(reader);
+
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name = enclosed_name(entry.header().identifier());
+        if name.is_some_and(|name| archive::is_member(&name, member)) {
+            io::copy(&mut entry, &mut writer)?;
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Reads every member of the archive to its end, and then the rest of `reader`
+pub fn test_archive(reader: Box<dyn Read>, position: &mut TestPosition) -> crate::Result<()> {
+    let mut archive = ar::Archive::new(reader);
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry?;
+        position.start_member(String::from_utf8_lossy(entry.header().identifier()).into_owned());
+        position.read_to_end(entry)?;
+    }
+
+    // The checksums of the compression formats are at the end of their data, past the end of the archive
+    io::copy(&mut archive.into_inner()?, &mut io::sink())?;
+    Ok(())
+}
+
+/// List contents of the archive read from `reader`, returning a vector of archive entries
+pub fn list_archive(reader: impl Read) -> impl Iterator<Item = crate::Result<FileInArchive>> {
+    struct Files<R: Read>(ar::Archive<R>);
+    impl<R: Read> Iterator for Files<R> {
+        type Item = crate::Result<FileInArchive>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                let entry = match self.0.next_entry()? {
+                    Ok(entry) => entry,
+                    Err(err) => return Some(Err(err.into())),
+                };
+                if let Some(path) = enclosed_name(entry.header().identifier()) {
+                    return Some(Ok(FileInArchive { path, is_dir: false }));
+                }
+            }
+        }
+    }
+
+    Files(ar::Archive::new(reader))
+}
+
+/// The name of an entry, or `None` if it isn't a plain file name, which would be unpacked elsewhere
+fn enclosed_name(identifier: &[u8]) -> Option<PathBuf> {
+    let path = PathBuf::from(String::from_utf8_lossy(identifier).into_owned());
+    let mut components = path.components();
+    let is_file_name = matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+
+    is_file_name.then_some(path)
+}
+
+/// Compresses the files given by `input_filenames` into the file given previously to `writer`.
+///
+/// Each file is stored with its file name, as ar archives can't hold directories.
+pub fn build_archive_from_paths<W, D>(input_filenames: &[PathBuf], writer: W, mut display_handle: D) -> crate::Result<W>
+where
+    W: Write,
+    D: Write,
+{
+    if let Some(directory) = input_filenames.iter().find(|path| path.is_dir()) {
+        let error = FinalError::with_title("Cannot build ar archive")
+            .detail("ar archives can only hold files, not directories")
+            .detail(format!("Directory: {}", to_utf(directory)))
+            .hint("Use a format that can hold directories, like .tar or .cpio");
+
+        return Err(error.into());
+    }
+
+    let mut builder = ar::Builder::new(writer);
+
+    for filename in input_filenames {
+        // This is printed for every file in `input_filenames` and has
+        // little importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        info!(@display_handle, inaccessible, "Compressing '{}'.", to_utf(filename));
+
+        // Safe unwrap, input shall be treated before
+        let name = to_utf(Path::new(filename.file_name().unwrap())).into_owned();
+
+        let mut file = fs::File::open(filename)?;
+        let metadata = file.metadata()?;
+        builder.append(&ar::Header::from_metadata(name.into_bytes(), &metadata), file.file_mut())?;
+    }
+
+    Ok(builder.into_inner()?)
+}
@@ -0,0 +1,550 @@
+//! Contains cpio-specific building and unpacking functions
+//!
+//! Archives in the "newc" format, used by the Linux initramfs, and in the older "odc" format can be unpacked,
+//! archives are built in the "newc" format.
+
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use fs_err as fs;
+
+use crate::{
+    archive::{self, filter::MemberFilter, rewrite::PathRewrite},
+    error::FinalError,
+    info,
+    integrity::TestPosition,
+    list::FileInArchive,
+    utils::{self, cd_into_same_dir_as, strip_cur_dir, to_utf, Bytes, FileVisibilityPolicy},
+};
+
+/// Magic of the "newc" format
+const NEWC_MAGIC: &[u8; 6] = b"070701";
+
+/// Magic of the "newc" format with checksums of the contents, which aren't verified
+const NEWC_CRC_MAGIC: &[u8; 6] = b"070702";
+
+/// Magic of the "odc" format, the portable format of POSIX.1
+const ODC_MAGIC: &[u8; 6] = b"070707";
+
+/// Length of "newc" headers, without the name
+const NEWC_HEADER_LEN: u64 = 110;
+
+/// Name of the entry that ends the archive
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+/// Names longer than this aren't valid paths on any system, the archive is corrupted
+const MAX_NAME_LEN: u64 = 64 * 1024;
+
+/// Bits of the mode that give the kind of file
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// The header of an entry
+struct Header {
+    dev: u64,
+    ino: u64,
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    nlink: u64,
+    mtime: u64,
+    size: u64,
+    name: Vec<u8>,
+}
+
+impl Header {
+    fn kind(&self) -> u32 {
+        self.mode & S_IFMT
+    }
+}
+
+/// Reads the header of the next entry, returns `None` at the end of the archive
+///
+/// The reader is left at the start of the contents, which are followed by `padding` bytes in "newc" archives.
+fn read_header(reader: &mut impl Read) -> crate::Result<Option<(Header, u64)>> {
+    let mut magic = [0; 6];
+    reader.read_exact(&mut magic)?;
+
+    let (header, padding) = match &magic {
+        NEWC_MAGIC | NEWC_CRC_MAGIC => {
+            // ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor, rdevminor, namesize, check
+            let mut fields = [0; 13 * 8];
+            reader.read_exact(&mut fields)?;
+            let field = |idx: usize| parse_field(&fields[idx * 8..][..8], 16);
+
+            let name_size = field(11)?;
+            let name = read_name(reader, name_size, padding(NEWC_HEADER_LEN + name_size))?;
+            let size = field(6)?;
+            let header = Header {
+                dev: field(7)? << 32 | field(8)?,
+                ino: field(0)?,
+                mode: field(1)? as u32,
+                uid: field(2)?,
+                gid: field(3)?,
+                nlink: field(4)?,
+                mtime: field(5)?,
+                size,
+                name,
+            };
+            (header, padding(size))
+        }
+        ODC_MAGIC => {
+            // dev, ino, mode, uid, gid, nlink, rdev, mtime, namesize, filesize
+            let mut fields = [0; 70];
+            reader.read_exact(&mut fields)?;
+            let field = |start: usize, end: usize| parse_field(&fields[start..end], 8);
+
+            let name = read_name(reader, field(53, 59)?, 0)?;
+            let header = Header {
+                dev: field(0, 6)?,
+                ino: field(6, 12)?,
+                mode: field(12, 18)? as u32,
+                uid: field(18, 24)?,
+                gid: field(24, 30)?,
+                nlink: field(30, 36)?,
+                mtime: field(42, 53)?,
+                size: field(59, 70)?,
+                name,
+            };
+            (header, 0)
+        }
+        _ => return Err(invalid_archive("Unknown header magic, only the newc and odc formats are supported").into()),
+    };
+
+    Ok((header.name != TRAILER).then_some((header, padding)))
+}
+
+/// Parses a number of a header, written in ASCII
+fn parse_field(field: &[u8], radix: u32) -> crate::Result<u64> {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| u64::from_str_radix(field, radix).ok())
+        .ok_or_else(|| invalid_archive("Invalid number in a header").into())
+}
+
+/// Reads the name of an entry, which ends with a NUL byte
+fn read_name(reader: &mut impl Read, size: u64, padding: u64) -> crate::Result<Vec<u8>> {
+    if size > MAX_NAME_LEN {
+        return Err(invalid_archive("Name too long").into());
+    }
+
+    let mut name = vec![0; size as usize];
+    reader.read_exact(&mut name)?;
+    if name.pop() != Some(0) {
+        return Err(invalid_archive("Name without a NUL terminator").into());
+    }
+    skip(reader, padding)?;
+
+    Ok(name)
+}
+
+/// The padding needed to align the end of something `len` bytes long to 4 bytes
+fn padding(len: u64) -> u64 {
+    (4 - len % 4) % 4
+}
+
+/// Skips the next `len` bytes of `reader`
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    if io::copy(&mut reader.take(len), &mut io::sink())? < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn invalid_archive(detail: &'static str) -> FinalError {
+    FinalError::with_title("Invalid cpio archive").detail(detail)
+}
+
+/// The path of an entry relative to the output folder, or `None` if it would be unpacked outside of it
+///
+/// Absolute paths are made relative to the output folder, like GNU cpio does with `--no-absolute-filenames`.
+fn enclosed_name(name: &[u8]) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in bytes_to_path(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    // "." is the output folder itself
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Whether one of the parents of `path` is a symlink unpacked earlier, writing through it could escape the output folder
+fn has_symlink_parent(output_folder: &Path, path: &Path) -> bool {
+    let mut parent = output_folder.to_path_buf();
+    path.parent().into_iter().flat_map(Path::components).any(|component| {
+        parent.push(component);
+        parent.is_symlink()
+    })
+}
+
+/// Unpacks the archive given by `archive` into the folder given by `into`.
+/// Assumes that output_folder is empty
+///
+/// Directories, regular files, symlinks and hard links are unpacked, device files, fifos and sockets are skipped.
+pub fn unpack_archive(
+    mut reader: Box<dyn Read>,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    mut display_handle: impl Write,
+) -> crate::Result<Vec<PathBuf>> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut files_unpacked = vec![];
+
+    // The first path of each hard linked file, by device and inode
+    let mut hard_links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+
+    // Permissions of directories are set at the end, so the read-only ones can still be filled
+    let mut directories = vec![];
+
+    while let Some((header, padding)) = read_header(&mut reader)? {
+        let name = enclosed_name(&header.name).filter(|name| filter.is_match(name));
+        let name = match name.and_then(|name| rewrite.apply(&name)) {
+            Some(name) if !has_symlink_parent(output_folder, &name) => name,
+            _ => {
+                skip(&mut reader, header.size + padding)?;
+                continue;
+            }
+        };
+        let file_path = output_folder.join(name);
+
+        if let Some(path) = file_path.parent() {
+            if !path.exists() {
+                fs::create_dir_all(path)?;
+            }
+        }
+        // A file that replaces a symlink must not be written through it
+        if file_path.is_symlink() {
+            fs::remove_file(&file_path)?;
+        }
+
+        match header.kind() {
+            S_IFDIR => {
+                // This is printed for every file in the archive and has little
+                // importance for most users, but would generate lots of
+                // spoken text for users using screen readers, braille displays
+                // and so on
+                info!(@display_handle, inaccessible, "Directory \"{}\" extracted.", file_path.display());
+                fs::create_dir_all(&file_path)?;
+                directories.push((file_path.clone(), header.mode));
+            }
+            S_IFREG => {
+                // same reason is in the directory case: long, often not needed text
+                info!(@display_handle, inaccessible, "{:?} extracted. ({})", strip_cur_dir(&file_path), Bytes::new(header.size));
+
+                // Hard links share the contents of the first one, which is stored with the last one by GNU cpio
+                let key = (header.dev, header.ino);
+                match hard_links.get(&key) {
+                    Some(first_path) if header.nlink > 1 => {
+                        if header.size > 0 {
+                            unpack_file(&mut reader, first_path, &header)?;
+                        }
+                        fs::hard_link(first_path, &file_path)?;
+                    }
+                    _ => {
+                        unpack_file(&mut reader, &file_path, &header)?;
+                        if header.nlink > 1 {
+                            hard_links.insert(key, file_path.clone());
+                        }
+                    }
+                }
+            }
+            #[cfg(unix)]
+            S_IFLNK => {
+                let mut target = vec![];
+                if (&mut reader).take(header.size).read_to_end(&mut target)? as u64 != header.size {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                info!(@display_handle, inaccessible, "{:?} extracted. (symlink)", strip_cur_dir(&file_path));
+                fs::os::unix::fs::symlink(bytes_to_path(&target), &file_path)?;
+            }
+            _ => {
+                skip(&mut reader, header.size + padding)?;
+                continue;
+            }
+        }
+
+        skip(&mut reader, padding)?;
+        files_unpacked.push(file_path);
+    }
+
+    #[cfg(unix)]
+    for (path, mode) in directories.into_iter().rev() {
+        __unix_set_permissions(&path, mode)?;
+    }
+
+    Ok(files_unpacked)
+}
+
+/// Writes the contents of the current entry to `file_path`
+fn unpack_file(reader: &mut impl Read, file_path: &Path, header: &Header) -> crate::Result<()> {
+    let mut output_file = fs::File::create(file_path)?;
+    if io::copy(&mut reader.take(header.size), &mut output_file)? < header.size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    output_file.file().set_modified(UNIX_EPOCH + Duration::from_secs(header.mtime))?;
+
+    #[cfg(unix)]
+    __unix_set_permissions(file_path, header.mode)?;
+
+    Ok(())
+}
+
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+pub fn cat_member(mut reader: Box<dyn Read>, member: &Path, mut writer: impl Write) -> crate::Result<bool> {
+    // The device and inode of the member, when it's a hard link whose contents are stored with another link
+    let mut hard_link = None;
+
+    while let Some((header, padding)) = read_header(&mut reader)? {
+        let is_member = enclosed_name(&header.name).is_some_and(|name| archive::is_member(&name, member));
+        let key = (header.dev, header.ino);
+        let has_member_contents = hard_link == Some(key) && header.size > 0;
+
+        if (is_member && hard_link.is_none()) || has_member_contents {
+            if header.kind() != S_IFREG {
+                return Err(archive::not_a_file_error(member).into());
+            }
+            // GNU cpio stores the contents of hard linked files with the last link only
+            if header.size == 0 && header.nlink > 1 && hard_link.is_none() {
+                hard_link = Some(key);
+                skip(&mut reader, padding)?;
+                continue;
+            }
+            if io::copy(&mut (&mut reader).take(header.size), &mut writer)? < header.size {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            return Ok(true);
+        }
+
+        skip(&mut reader, header.size + padding)?;
+    }
+
+    // Every link was empty, so was the file
+    Ok(hard_link.is_some())
+}
+
+/// Reads every member of the archive to its end, and then the rest of `reader`
+pub fn test_archive(mut reader: Box<dyn Read>, position: &mut TestPosition) -> crate::Result<()> {
+    while let Some((header, padding)) = read_header(&mut reader)? {
+        position.start_member(bytes_to_path(&header.name));
+        let mut contents = (&mut reader).take(header.size);
+        position.read_to_end(&mut contents)?;
+        if contents.limit() > 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        skip(&mut reader, padding)?;
+    }
+
+    // The checksums of the compression formats are at the end of their data, past the end of the archive
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(())
+}
+
+/// List contents of the archive read from `reader`, returning a vector of archive entries
+pub fn list_archive(reader: impl Read) -> impl Iterator<Item = crate::Result<FileInArchive>> {
+    struct Files<R> {
+        reader: R,
+        finished: bool,
+    }
+    impl<R: Read> Iterator for Files<R> {
+        type Item = crate::Result<FileInArchive>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while !self.finished {
+                let header = read_header(&mut self.reader).and_then(|header| {
+                    if let Some((header, padding)) = &header {
+                        skip(&mut self.reader, header.size + padding)?;
+                    }
+                    Ok(header)
+                });
+
+                match header {
+                    Ok(Some((header, _))) => {
+                        if let Some(path) = enclosed_name(&header.name) {
+                            return Some(Ok(FileInArchive { path, is_dir: header.kind() == S_IFDIR }));
+                        }
+                    }
+                    Ok(None) => self.finished = true,
+                    Err(err) => {
+                        self.finished = true;
+                        return Some(Err(err));
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    Files { reader, finished: false }
+}
+
+/// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// Symlinks are followed like the tar archives do, so hard links aren't stored either.
+pub fn build_archive_from_paths<W, D>(
+    input_filenames: &[PathBuf],
+    mut writer: W,
+    file_visibility_policy: FileVisibilityPolicy,
+    mut display_handle: D,
+) -> crate::Result<W>
+where
+    W: Write,
+    D: Write,
+{
+    // Only needs to be unique, as there are no hard links
+    let mut ino = 0;
+
+    for filename in input_filenames {
+        let previous_location = cd_into_same_dir_as(filename)?;
+
+        // Safe unwrap, input shall be treated before
+        let filename = filename.file_name().unwrap();
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let entry = entry?;
+            let path = entry.path();
+
+            // This is printed for every file in `input_filenames` and has
+            // little importance for most users, but would generate lots of
+            // spoken text for users using screen readers, braille displays
+            // and so on
+            info!(@display_handle, inaccessible, "Compressing '{}'.", to_utf(path));
+
+            let metadata = match fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
+                        // This path is for a broken symlink
+                        // We just ignore it
+                        continue;
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            ino += 1;
+            let header = header_from_metadata(ino, path, &metadata);
+
+            if metadata.is_dir() {
+                write_header(&mut writer, &header)?;
+                continue;
+            }
+            if header.size > u32::MAX as u64 {
+                let error = FinalError::with_title("Cannot build cpio archive")
+                    .detail("cpio archives can't hold files of 4 GiB or more")
+                    .detail(format!("File too big: {}", to_utf(path)));
+
+                return Err(error.into());
+            }
+
+            write_header(&mut writer, &header)?;
+            let file = fs::File::open(path)?;
+            if io::copy(&mut file.take(header.size), &mut writer)? < header.size {
+                return Err(FinalError::with_title("Could not create archive")
+                    .detail(format!("'{}' was truncated while it was being read", to_utf(path)))
+                    .into());
+            }
+            writer.write_all(&[0; 3][..padding(header.size) as usize])?;
+        }
+        env::set_current_dir(previous_location)?;
+    }
+
+    let trailer =
+        Header { dev: 0, ino: 0, mode: 0, uid: 0, gid: 0, nlink: 1, mtime: 0, size: 0, name: TRAILER.to_vec() };
+    write_header(&mut writer, &trailer)?;
+
+    Ok(writer)
+}
+
+#[cfg(unix)]
+fn header_from_metadata(ino: u64, path: &Path, metadata: &std::fs::Metadata) -> Header {
+    use std::os::unix::{ffi::OsStrExt, fs::MetadataExt};
+
+    Header {
+        dev: 0,
+        ino,
+        mode: metadata.mode(),
+        uid: metadata.uid().into(),
+        gid: metadata.gid().into(),
+        nlink: if metadata.is_dir() { 2 } else { 1 },
+        mtime: metadata.mtime().clamp(0, u32::MAX.into()) as u64,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        name: path.as_os_str().as_bytes().to_vec(),
+    }
+}
+
+#[cfg(not(unix))]
+fn header_from_metadata(ino: u64, path: &Path, metadata: &std::fs::Metadata) -> Header {
+    let mtime = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+
+    Header {
+        dev: 0,
+        ino,
+        mode: if metadata.is_dir() { S_IFDIR | 0o755 } else { S_IFREG | 0o644 },
+        uid: 0,
+        gid: 0,
+        nlink: if metadata.is_dir() { 2 } else { 1 },
+        mtime: mtime.map_or(0, |mtime| mtime.as_secs().min(u32::MAX.into())),
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        name: to_utf(path).replace('\\', "/").into_bytes(),
+    }
+}
+
+/// Writes a "newc" header, followed by the name and its padding
+fn write_header(writer: &mut impl Write, header: &Header) -> io::Result<()> {
+    let name_size = header.name.len() as u64 + 1;
+    let fields = [
+        header.ino,
+        header.mode.into(),
+        header.uid,
+        header.gid,
+        header.nlink,
+        header.mtime,
+        header.size,
+        header.dev >> 32,
+        header.dev & 0xFFFF_FFFF,
+        0,
+        0,
+        name_size,
+        0,
+    ];
+
+    writer.write_all(NEWC_MAGIC)?;
+    for field in fields {
+        write!(writer, "{:08X}", field)?;
+    }
+    writer.write_all(&header.name)?;
+    writer.write_all(&[0; 4][..1 + padding(NEWC_HEADER_LEN + name_size) as usize])
+}
+
+#[cfg(unix)]
+fn __unix_set_permissions(file_path: &Path, mode: u32) -> crate::Result<()> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    fs::set_permissions(file_path, Permissions::from_mode(mode & 0o7777))?;
+
+    Ok(())
+}
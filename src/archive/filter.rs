@@ -0,0 +1,127 @@
+//! Selection of the members of archives that are unpacked
+
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::error::FinalError;
+
+/// Selects the members of an archive by paths or globs, like "src/**/*.rs"
+///
+/// The patterns are matched against the paths shown by `ouch list`, `*` doesn't match `/` but `**` does.
+/// The members inside of a matched directory are matched too.
+#[derive(Debug, Clone, Default)]
+pub struct MemberFilter {
+    /// Patterns of the members to unpack, all of them are unpacked if there are no patterns
+    members: Vec<String>,
+    include: GlobSet,
+    exclude: GlobSet,
+    /// Whether each pattern of `members` matched a member of the archive
+    found: Vec<Cell<bool>>,
+}
+
+impl MemberFilter {
+    /// Creates a filter that selects the `members` of the archive that don't match any of the `exclude` patterns
+    pub fn new(members: &[String], exclude: &[String]) -> crate::Result<Self> {
+        Ok(Self {
+            members: members.to_vec(),
+            include: build_glob_set(members)?,
+            exclude: build_glob_set(exclude)?,
+            found: vec![Cell::new(false); members.len()],
+        })
+    }
+
+    /// Whether only some of the members are selected
+    pub fn is_selective(&self) -> bool {
+        !self.members.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Whether the member at `path` is selected
+    pub fn is_match(&self, path: &Path) -> bool {
+        let mut matches = vec![];
+        let mut excluded = false;
+
+        // Directories may be stored with a trailing slash, which is dropped by `components`
+        let path = path.components().collect::<PathBuf>();
+        for ancestor in path.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            matches.extend(self.include.matches(ancestor));
+            excluded |= self.exclude.is_match(ancestor);
+        }
+
+        let selected = (self.members.is_empty() || !matches.is_empty()) && !excluded;
+        // The patterns only count as found by the members that are unpacked
+        if selected {
+            for index in matches {
+                self.found[index].set(true);
+            }
+        }
+        selected
+    }
+
+    /// Fails if some of the patterns given didn't match any of the members seen by `is_match`
+    pub fn check_all_found(&self) -> crate::Result<()> {
+        let missing: Vec<_> = self.members.iter().zip(&self.found).filter(|(_, found)| !found.get()).collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut error = FinalError::with_title("Could not find the members to unpack");
+        for (member, _) in missing {
+            error = error.detail(format!("'{}' didn't match any member of the archive", member));
+        }
+        Err(error.hint("Use 'ouch list' to see the paths of the members").into())
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> crate::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern).literal_separator(true).build().map_err(|err| {
+            FinalError::with_title(format!("Invalid glob '{}'", pattern)).detail(err.kind().to_string())
+        })?;
+        builder.add(glob);
+    }
+
+    Ok(builder.build().expect("globs were already validated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(members: &[&str], exclude: &[&str]) -> MemberFilter {
+        let to_strings = |patterns: &[&str]| patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>();
+        MemberFilter::new(&to_strings(members), &to_strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn test_member_filter() {
+        let everything = filter(&[], &[]);
+        assert!(!everything.is_selective());
+        assert!(everything.is_match(Path::new("src/main.rs")));
+
+        // Members that are excluded don't count as found
+        let excluded = filter(&["docs"], &["docs"]);
+        assert!(!excluded.is_match(Path::new("docs/")));
+        assert!(!excluded.is_match(Path::new("docs/README.md")));
+        assert!(excluded.check_all_found().is_err());
+
+        let filter = filter(&["src/**/*.rs", "docs", "*.md"], &["src/generated"]);
+        assert!(filter.is_match(Path::new("src/main.rs")));
+        assert!(filter.is_match(Path::new("src/archive/tar.rs")));
+        assert!(!filter.is_match(Path::new("src/generated/opts.rs")));
+        // The members of a matched directory
+        assert!(filter.is_match(Path::new("docs/")));
+        assert!(filter.is_match(Path::new("docs/guide/index.html")));
+        // `*` doesn't match `/`
+        assert!(!filter.is_match(Path::new("docs.txt")));
+        assert!(!filter.is_match(Path::new("src/README.md")));
+        assert!(filter.check_all_found().is_err());
+
+        assert!(filter.is_match(Path::new("README.md")));
+        assert!(filter.check_all_found().is_ok());
+    }
+}
@@ -1,4 +1,28 @@
 //! Archive compression algorithms
 
+use std::path::{Component, Path};
+
+use crate::error::FinalError;
+
+pub mod ar;
+pub mod cpio;
+pub mod filter;
+pub mod rar;
+pub mod rewrite;
+pub mod sevenz;
 pub mod tar;
 pub mod zip;
+
+/// Whether `path`, the path of a member of an archive, is the `member` given by the user
+///
+/// A leading `./` and trailing slashes are ignored, like in the paths shown by `ouch list`.
+pub fn is_member(path: &Path, member: &Path) -> bool {
+    let components = |path| Path::components(path).filter(|component| *component != Component::CurDir);
+    components(path).eq(components(member))
+}
+
+/// Error for a `member` written to stdout that isn't a file, like a directory or a link
+pub fn not_a_file_error(member: &Path) -> FinalError {
+    FinalError::with_title(format!("Cannot write '{}' to stdout", member.display()))
+        .detail("Only the regular files of archives can be written to stdout, not directories or links")
+}
@@ -0,0 +1,179 @@
+//! Contains RAR-specific unpacking functions, RAR archives can't be created
+
+use std::{
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use fs_err as fs;
+use unrar::{error::Code, Archive};
+
+use crate::{
+    archive::{
+        filter::MemberFilter,
+        is_member, not_a_file_error,
+        rewrite::{self, PathRewrite},
+    },
+    error::FinalError,
+    info,
+    integrity::TestPosition,
+    list::FileInArchive,
+    utils::{strip_cur_dir, Bytes},
+};
+
+/// Creates the archive handle, with the password if there's one
+fn archive<'a>(archive_path: &'a Path, password: Option<&'a [u8]>) -> Archive<'a> {
+    match password {
+        Some(password) => Archive::with_password(archive_path, password),
+        None => Archive::new(archive_path),
+    }
+}
+
+/// Unpacks the archive at `archive_path` into the folder given by `output_folder`.
+/// Assumes that output_folder is empty
+///
+/// Both RAR4 and RAR5 archives are supported, the library only reads archives from paths.
+pub fn unpack_archive(
+    archive_path: &Path,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    password: Option<&[u8]>,
+    mut display_handle: impl Write,
+) -> crate::Result<Vec<PathBuf>> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut unpacked_files = vec![];
+    let mut archive = archive(archive_path, password).open_for_processing()?;
+
+    while let Some(header) = archive.read_header()? {
+        let entry = header.entry();
+        let path = Some(&entry.filename).filter(|path| is_enclosed(path) && filter.is_match(path));
+        let file_path = match path.and_then(|path| rewrite.apply(path)) {
+            Some(path) => output_folder.join(path),
+            None => {
+                archive = header.skip()?;
+                continue;
+            }
+        };
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        if entry.is_directory() {
+            info!(@display_handle, inaccessible, "Directory \"{}\" extracted.", file_path.display());
+        } else {
+            info!(@display_handle, inaccessible, "{:?} extracted. ({})", strip_cur_dir(&file_path).display(), Bytes::new(entry.unpacked_size));
+        }
+
+        // The permissions and the modification time are restored by the library
+        let (is_encrypted, entry_name) = (entry.is_encrypted(), entry.filename.clone());
+        let extracted = if !rewrite.is_active() {
+            header.extract_with_base(output_folder)
+        } else if entry.is_directory() {
+            fs::create_dir_all(&file_path)?;
+            header.skip()
+        } else {
+            rewrite::create_parent_dirs(output_folder, &file_path)?;
+            header.extract_to(&file_path)
+        };
+        archive = extracted.map_err(|err| {
+            match err.code {
+                // Encrypted files of RAR4 archives fail the checksum when the password is wrong
+                Code::BadData if is_encrypted => {
+                    FinalError::with_title("Cannot unpack encrypted rar archive")
+                        .detail(format!("Invalid password or corrupted file \"{}\"", entry_name.display()))
+                        .into()
+                }
+                _ => crate::Error::from(err),
+            }
+        })?;
+        unpacked_files.push(file_path);
+    }
+
+    Ok(unpacked_files)
+}
+
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+///
+/// The library only extracts files to paths or into memory, so the member is read into memory first.
+pub fn cat_member(
+    archive_path: &Path,
+    member: &Path,
+    password: Option<&[u8]>,
+    mut writer: impl Write,
+) -> crate::Result<bool> {
+    let mut archive = archive(archive_path, password).open_for_processing()?;
+
+    while let Some(header) = archive.read_header()? {
+        let entry = header.entry();
+        if !is_member(&entry.filename, member) {
+            archive = header.skip()?;
+            continue;
+        }
+        if entry.is_directory() {
+            return Err(not_a_file_error(member).into());
+        }
+
+        let (contents, _) = header.read()?;
+        writer.write_all(&contents)?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Tests every member of the archive at `archive_path`, the library verifies their checksums
+pub fn test_archive(archive_path: &Path, password: Option<&[u8]>, position: &mut TestPosition) -> crate::Result<()> {
+    let mut archive = archive(archive_path, password).open_for_processing()?;
+    while let Some(header) = archive.read_header()? {
+        position.start_member(&header.entry().filename);
+        archive = header.test()?;
+        position.finish_member();
+    }
+    Ok(())
+}
+
+/// Checks if the archive at `archive_path` has encrypted files or headers, which require a password to unpack it
+pub fn has_encrypted_files(archive_path: &Path) -> crate::Result<bool> {
+    let mut archive = Archive::new(archive_path).open_for_listing()?;
+    if archive.has_encrypted_headers() {
+        return Ok(true);
+    }
+
+    for entry in &mut archive {
+        if entry?.is_encrypted() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// List contents of the archive at `archive_path`, returning a vector of archive entries
+///
+/// `password` is needed to read the names of RAR5 archives with encrypted headers.
+pub fn list_archive(
+    archive_path: &Path,
+    password: Option<&[u8]>,
+) -> crate::Result<impl Iterator<Item = crate::Result<FileInArchive>>> {
+    let archive = archive(archive_path, password).open_for_listing()?;
+
+    // The entries are collected before returning, so the archive can be a temporary file
+    let files: Vec<_> = archive
+        .filter_map(|entry| {
+            match entry {
+                Ok(entry) if !is_enclosed(&entry.filename) => None,
+                Ok(entry) => Some(Ok(FileInArchive { is_dir: entry.is_directory(), path: entry.filename })),
+                Err(err) => Some(Err(err.into())),
+            }
+        })
+        .collect();
+
+    Ok(files.into_iter())
+}
+
+/// Checks if the path would be unpacked inside of the output folder
+fn is_enclosed(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
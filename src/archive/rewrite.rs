@@ -0,0 +1,202 @@
+//! Rewriting of the paths of archive members before they're unpacked
+
+use std::path::{Component, Path, PathBuf};
+
+use fs_err as fs;
+use regex::{Regex, RegexBuilder};
+
+use crate::error::FinalError;
+
+/// Rewrites the paths of the members of archives, with `--strip-components` and `--transform`
+///
+/// The leading components are stripped first, then the sed-like substitution is applied to what's left.
+#[derive(Debug, Clone, Default)]
+pub struct PathRewrite {
+    /// Number of leading components removed from the paths
+    strip_components: usize,
+    transform: Option<Transform>,
+}
+
+/// A substitution like sed's `s/regex/replacement/flags`
+#[derive(Debug, Clone)]
+struct Transform {
+    regex: Regex,
+    /// The replacement, in the syntax of `Regex::replace`
+    replacement: String,
+    /// Whether every match is replaced, instead of only the first one
+    global: bool,
+}
+
+impl PathRewrite {
+    /// Creates a rewrite that strips `strip_components` and applies the `transform` expression, like 's/^old/new/'
+    pub fn new(strip_components: usize, transform: Option<&str>) -> crate::Result<Self> {
+        let transform = transform.map(parse_transform).transpose()?;
+        Ok(Self { strip_components, transform })
+    }
+
+    /// Whether the paths are rewritten at all
+    pub fn is_active(&self) -> bool {
+        self.strip_components > 0 || self.transform.is_some()
+    }
+
+    /// The rewritten path of a member, or `None` if the member should be skipped
+    ///
+    /// Members are skipped when nothing is left of their path, or when it would be unpacked outside of the output
+    /// folder, the paths rewritten are always relative. Paths are kept as they are if nothing is rewritten.
+    pub fn apply(&self, path: &Path) -> Option<PathBuf> {
+        if !self.is_active() {
+            return Some(path.to_path_buf());
+        }
+
+        let path: PathBuf = enclosed_components(path)?.skip(self.strip_components).collect();
+
+        let path = match &self.transform {
+            Some(Transform { regex, replacement, global }) => {
+                let path = path.to_string_lossy();
+                let path = if *global {
+                    regex.replace_all(&path, replacement.as_str())
+                } else {
+                    regex.replace(&path, replacement.as_str())
+                };
+                let path: PathBuf = enclosed_components(Path::new(path.as_ref()))?.collect();
+                path
+            }
+            None => path,
+        };
+
+        (!path.as_os_str().is_empty()).then_some(path)
+    }
+}
+
+/// Creates the parent directories of the rewritten `file_path`, failing if they lead outside of `output_folder`
+/// through a symlink unpacked before
+pub fn create_parent_dirs(output_folder: &Path, file_path: &Path) -> crate::Result<()> {
+    let parent = file_path.parent().expect("file_path is inside of output_folder");
+    fs::create_dir_all(parent)?;
+    if !fs::canonicalize(parent)?.starts_with(fs::canonicalize(output_folder)?) {
+        let error = FinalError::with_title("Cannot unpack archive")
+            .detail(format!("'{}' would be unpacked outside of the output folder", file_path.display()));
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// The named components of `path`, or `None` if it has a `..`
+fn enclosed_components(path: &Path) -> Option<impl Iterator<Item = Component<'_>>> {
+    if path.components().any(|component| component == Component::ParentDir) {
+        return None;
+    }
+    Some(path.components().filter(|component| matches!(component, Component::Normal(_))))
+}
+
+/// Parses a sed-like substitution, `&` and `\1` to `\9` in the replacement are the match and its groups
+fn parse_transform(expression: &str) -> crate::Result<Transform> {
+    let invalid = || {
+        FinalError::with_title(format!("Invalid transform expression '{}'", expression))
+            .detail("Expected a substitution like 's/^old/new/', optionally followed by the flags 'g' and 'i'")
+    };
+
+    let mut chars = expression.chars();
+    if chars.next() != Some('s') {
+        return Err(invalid().into());
+    }
+    let delimiter = chars.next().ok_or_else(invalid)?;
+
+    // Splits the expression on the delimiters that aren't escaped, other escapes are kept for the next steps
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for char in chars {
+        let part = parts.last_mut().expect("there is always a part");
+        if escaped {
+            if char != delimiter {
+                part.push('\\');
+            }
+            part.push(char);
+            escaped = false;
+        } else if char == '\\' {
+            escaped = true;
+        } else if char == delimiter {
+            parts.push(String::new());
+        } else {
+            part.push(char);
+        }
+    }
+    let (pattern, replacement, flags) = match parts.as_slice() {
+        [pattern, replacement, flags] if !escaped => (pattern, replacement, flags),
+        _ => return Err(invalid().into()),
+    };
+
+    let (mut global, mut case_insensitive) = (false, false);
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'i' => case_insensitive = true,
+            _ => return Err(invalid().detail(format!("Unknown flag '{}'", flag)).into()),
+        }
+    }
+
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| invalid().detail(err.to_string()))?;
+
+    Ok(Transform { regex, replacement: sed_replacement_to_regex(replacement), global })
+}
+
+/// Converts the syntax of sed replacements into the syntax of `Regex::replace`
+fn sed_replacement_to_regex(replacement: &str) -> String {
+    let mut converted = String::new();
+    let mut chars = replacement.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                match chars.next() {
+                    Some(group @ '0'..='9') => converted.push_str(&format!("${{{}}}", group)),
+                    Some('$') => converted.push_str("$$"),
+                    Some(escaped) => converted.push(escaped),
+                    None => converted.push('\\'),
+                }
+            }
+            '&' => converted.push_str("${0}"),
+            '$' => converted.push_str("$$"),
+            _ => converted.push(char),
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(strip_components: usize, transform: Option<&str>, path: &str) -> Option<PathBuf> {
+        PathRewrite::new(strip_components, transform).unwrap().apply(Path::new(path))
+    }
+
+    #[test]
+    fn test_strip_components() {
+        assert_eq!(rewrite(1, None, "project-1.2.3/src/main.rs"), Some(PathBuf::from("src/main.rs")));
+        assert_eq!(rewrite(1, None, "./project-1.2.3/src/"), Some(PathBuf::from("src")));
+        // Nothing is left of the top directory itself
+        assert_eq!(rewrite(1, None, "project-1.2.3/"), None);
+        assert_eq!(rewrite(1, None, "project/../../escape"), None);
+    }
+
+    #[test]
+    fn test_transform() {
+        assert_eq!(
+            rewrite(0, Some(r"s/^project-[^\/]*/project/"), "project-1.2.3/a"),
+            Some(PathBuf::from("project/a"))
+        );
+        assert_eq!(rewrite(1, Some(r"s|\.txt$|.md|"), "root/docs/a.txt"), Some(PathBuf::from("docs/a.md")));
+        assert_eq!(rewrite(0, Some("s/a/[&]/g"), "banana"), Some(PathBuf::from("b[a]n[a]n[a]")));
+        assert_eq!(rewrite(0, Some(r"s/(.*)\/(.*)/\2\/\1/"), "dir/file"), Some(PathBuf::from("file/dir")));
+        assert_eq!(rewrite(0, Some("s/A/b/i"), "a$"), Some(PathBuf::from("b$")));
+        assert_eq!(rewrite(0, Some("s/.*//"), "file"), None);
+        assert_eq!(rewrite(0, Some("s/^/..\\//"), "file"), None);
+
+        for invalid in ["x/a/b/", "s/a/b", "s/a/b/q", "s/(/b/"] {
+            assert!(PathRewrite::new(0, Some(invalid)).is_err());
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! Contains 7z-specific building and unpacking functions
+
+use std::{
+    env,
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use fs_err as fs;
+use sevenz_rust2::{encoder_options::Lzma2Options, ArchiveEntry, ArchiveReader, ArchiveWriter, Password, SourceReader};
+
+use crate::{
+    archive::{self, filter::MemberFilter, rewrite::PathRewrite},
+    error::FinalError,
+    info,
+    integrity::TestPosition,
+    list::FileInArchive,
+    utils::{
+        self, cd_into_same_dir_as, concatenate_os_str_list, get_invalid_utf8_paths, strip_cur_dir, to_utf, Bytes,
+        FileVisibilityPolicy,
+    },
+};
+
+/// Windows attribute of directories
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+/// Windows attribute of regular files
+const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+
+/// Set by p7zip and 7-Zip when the high 16 bits of the attributes hold the Unix mode
+const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+
+/// Opens the 7z archive read from `reader`, which must be seekable as the headers are at its end
+fn open_archive<R: Read + Seek>(reader: R) -> crate::Result<ArchiveReader<R>> {
+    Ok(ArchiveReader::new(reader, Password::empty())?)
+}
+
+/// Unpacks the archive read from `reader` into the folder given by `output_folder`.
+/// Assumes that output_folder is empty
+///
+/// Both solid and non-solid archives are supported, solid blocks are decoded in a single pass.
+pub fn unpack_archive<R, D>(
+    reader: R,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    mut display_handle: D,
+) -> crate::Result<Vec<PathBuf>>
+where
+    R: Read + Seek,
+    D: Write,
+{
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut archive = open_archive(reader)?;
+    let mut unpacked_files = Vec::with_capacity(archive.archive().files.len());
+    let mut unpack_error = None;
+
+    // The callback can only return 7z errors, so our own error is kept aside and the iteration stopped
+    archive.for_each_entries(|entry, reader| {
+        match unpack_entry(entry, reader, output_folder, filter, rewrite, &mut display_handle) {
+            Ok(Some(file_path)) => unpacked_files.push(file_path),
+            Ok(None) => {}
+            Err(err) => {
+                unpack_error = Some(err);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    })?;
+
+    match unpack_error {
+        Some(err) => Err(err),
+        None => Ok(unpacked_files),
+    }
+}
+
+/// Unpacks a single entry, returns `None` if it was skipped
+fn unpack_entry(
+    entry: &ArchiveEntry,
+    reader: &mut dyn Read,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    mut display_handle: impl Write,
+) -> crate::Result<Option<PathBuf>> {
+    // Deleted entries of updated archives have nothing to unpack
+    if entry.is_anti_item() {
+        return Ok(None);
+    }
+    let file_path = enclosed_name(entry.name()).filter(|path| filter.is_match(path));
+    let file_path = match file_path.and_then(|path| rewrite.apply(&path)) {
+        Some(path) => output_folder.join(path),
+        None => {
+            // The files of solid blocks are read one after the other, the skipped ones must still be read through
+            io::copy(reader, &mut io::sink())?;
+            return Ok(None);
+        }
+    };
+
+    if entry.is_directory() {
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        info!(@display_handle, inaccessible, "Directory \"{}\" extracted.", file_path.display());
+        fs::create_dir_all(&file_path)?;
+    } else {
+        if let Some(path) = file_path.parent() {
+            if !path.exists() {
+                fs::create_dir_all(path)?;
+            }
+        }
+
+        // same reason is in the directory case: long, often not needed text
+        info!(@display_handle, inaccessible, "{:?} extracted. ({})", strip_cur_dir(&file_path).display(), Bytes::new(entry.size()));
+
+        let mut output_file = fs::File::create(&file_path)?;
+        io::copy(reader, &mut output_file)?;
+
+        if entry.has_last_modified_date {
+            output_file.file().set_modified(SystemTime::from(entry.last_modified_date()))?;
+        }
+    }
+
+    #[cfg(unix)]
+    __unix_set_permissions(&file_path, entry)?;
+
+    Ok(Some(file_path))
+}
+
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+pub fn cat_member<R: Read + Seek>(reader: R, member: &Path, mut writer: impl Write) -> crate::Result<bool> {
+    let mut archive = open_archive(reader)?;
+    let mut found = false;
+    let mut cat_error = None;
+
+    // The callback can only return 7z errors, so our own error is kept aside and the iteration stopped
+    archive.for_each_entries(|entry, reader| {
+        let is_member =
+            !entry.is_anti_item() && enclosed_name(entry.name()).is_some_and(|name| archive::is_member(&name, member));
+        if !is_member {
+            // The files of solid blocks are read one after the other, the skipped ones must still be read through
+            io::copy(reader, &mut io::sink())?;
+            return Ok(true);
+        }
+
+        found = true;
+        if entry.is_directory() {
+            cat_error = Some(archive::not_a_file_error(member).into());
+        } else if let Err(err) = io::copy(reader, &mut writer) {
+            cat_error = Some(err.into());
+        }
+        Ok(false)
+    })?;
+
+    match cat_error {
+        Some(err) => Err(err),
+        None => Ok(found),
+    }
+}
+
+/// Reads every member of the archive to its end, which verifies their CRC32
+pub fn test_archive<R: Read + Seek>(reader: R, position: &mut TestPosition) -> crate::Result<()> {
+    let mut archive = open_archive(reader)?;
+    let mut test_error = None;
+
+    // The callback can only return 7z errors, so our own error is kept aside and the iteration stopped
+    archive.for_each_entries(|entry, reader| {
+        position.start_member(entry.name());
+        match position.read_to_end(reader) {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                test_error = Some(err);
+                Ok(false)
+            }
+        }
+    })?;
+
+    match test_error {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// List contents of the archive read from `reader`, returning a vector of archive entries
+pub fn list_archive<R>(reader: R) -> crate::Result<impl Iterator<Item = crate::Result<FileInArchive>>>
+where
+    R: Read + Seek,
+{
+    // The entries are all in the headers, which are read when opening the archive
+    let archive = open_archive(reader)?;
+
+    let files: Vec<_> = archive
+        .archive()
+        .files
+        .iter()
+        .filter(|entry| !entry.is_anti_item())
+        .filter_map(|entry| {
+            let path = enclosed_name(entry.name())?;
+            Some(Ok(FileInArchive { path, is_dir: entry.is_directory() }))
+        })
+        .collect();
+
+    Ok(files.into_iter())
+}
+
+/// The path of an entry, or `None` if it would be unpacked outside of the output folder, or into the folder itself
+fn enclosed_name(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    let is_enclosed = path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    let has_name = path.components().any(|component| matches!(component, Component::Normal(_)));
+
+    (is_enclosed && has_name).then_some(path)
+}
+
+/// A file that is only opened when it's first read, and closed when it's fully read
+///
+/// The contents of a solid block are read one file after the other, this way only one of them is open at a time.
+struct LazyFile {
+    path: PathBuf,
+    file: Option<fs::File>,
+    finished: bool,
+}
+
+impl Read for LazyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(fs::File::open(&self.path)?),
+        };
+
+        let read = file.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.file = None;
+            self.finished = true;
+        }
+        Ok(read)
+    }
+}
+
+/// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// The files are compressed with LZMA2, the files of each input are put together in a solid block.
+/// The headers are written at the end and their position is written at the start, so `writer` needs to be seekable.
+pub fn build_archive_from_paths<W, D>(
+    input_filenames: &[PathBuf],
+    writer: W,
+    compression_level: Option<u32>,
+    file_visibility_policy: FileVisibilityPolicy,
+    mut display_handle: D,
+) -> crate::Result<W>
+where
+    W: Write + Seek,
+    D: Write,
+{
+    let mut writer = ArchiveWriter::new(writer)?;
+    if let Some(level) = compression_level {
+        writer.set_content_methods(vec![Lzma2Options::from_level(level).into()]);
+    }
+
+    // Vec of any filename that failed the UTF-8 check
+    let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
+
+    if !invalid_unicode_filenames.is_empty() {
+        let error = FinalError::with_title("Cannot build 7z archive")
+            .detail("7z archives require files to have valid UTF-8 paths")
+            .detail(format!("Files with invalid paths: {}", concatenate_os_str_list(&invalid_unicode_filenames)));
+
+        return Err(error.into());
+    }
+
+    // The last empty file is written after every other entry without contents, see below
+    let mut last_empty_file = None;
+
+    for filename in input_filenames {
+        let previous_location = cd_into_same_dir_as(filename)?;
+
+        // Safe unwrap, input shall be treated before
+        let filename = filename.file_name().unwrap();
+
+        // Files with contents that go into the solid block of this input
+        let mut solid_entries = vec![];
+        let mut solid_readers = vec![];
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let entry = entry?;
+            let path = entry.path();
+
+            // This is printed for every file in `input_filenames` and has
+            // little importance for most users, but would generate lots of
+            // spoken text for users using screen readers, braille displays
+            // and so on
+            info!(@display_handle, inaccessible, "Compressing '{}'.", to_utf(path));
+
+            let metadata = match fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
+                        // This path is for a broken symlink
+                        // We just ignore it
+                        continue;
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            let mut archive_entry = ArchiveEntry::from_path(path, path.to_str().unwrap().to_owned());
+            set_attributes(&mut archive_entry, &metadata);
+
+            // Directories and empty files have no contents
+            if metadata.is_dir() {
+                writer.push_archive_entry::<fs::File>(archive_entry, None)?;
+            } else if metadata.len() == 0 {
+                if let Some(empty_file) = last_empty_file.replace(archive_entry) {
+                    writer.push_archive_entry::<fs::File>(empty_file, None)?;
+                }
+            } else {
+                solid_entries.push(archive_entry);
+                solid_readers.push(SourceReader::new(LazyFile { path: path.to_owned(), file: None, finished: false }));
+            }
+        }
+
+        if !solid_entries.is_empty() {
+            writer.push_archive_entries(solid_entries, solid_readers)?;
+        }
+
+        env::set_current_dir(previous_location)?;
+    }
+
+    // The writer sizes the bitset that tells the empty files apart from the directories by its last empty file,
+    // instead of by the number of entries without contents, so the archive is corrupt unless that file comes last
+    if let Some(empty_file) = last_empty_file {
+        writer.push_archive_entry::<fs::File>(empty_file, None)?;
+    }
+
+    Ok(writer.finish()?)
+}
+
+/// Stores the kind of the file, and its Unix mode like p7zip does
+fn set_attributes(entry: &mut ArchiveEntry, metadata: &std::fs::Metadata) {
+    let mut attributes = if metadata.is_dir() { FILE_ATTRIBUTE_DIRECTORY } else { FILE_ATTRIBUTE_ARCHIVE };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        attributes |= FILE_ATTRIBUTE_UNIX_EXTENSION | (metadata.permissions().mode() << 16);
+    }
+
+    entry.has_windows_attributes = true;
+    entry.windows_attributes = attributes;
+}
+
+#[cfg(unix)]
+fn __unix_set_permissions(file_path: &Path, entry: &ArchiveEntry) -> crate::Result<()> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    let attributes = entry.windows_attributes();
+    if entry.has_windows_attributes && attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0 {
+        fs::set_permissions(file_path, Permissions::from_mode(attributes >> 16))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enclosed_name() {
+        assert_eq!(enclosed_name("dir/file"), Some(PathBuf::from("dir/file")));
+        assert_eq!(enclosed_name("./dir/"), Some(PathBuf::from("./dir/")));
+        // Names that would be unpacked outside of the output folder, or into the folder itself
+        assert_eq!(enclosed_name("../file"), None);
+        assert_eq!(enclosed_name("/etc/passwd"), None);
+        assert_eq!(enclosed_name(""), None);
+        assert_eq!(enclosed_name("."), None);
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::{
     env,
-    io::prelude::*,
+    io::{self, prelude::*},
     path::{Path, PathBuf},
     sync::mpsc::{self, Receiver},
     thread,
@@ -12,10 +12,17 @@ use fs_err as fs;
 use tar;
 
 use crate::{
+    archive::{
+        self,
+        filter::MemberFilter,
+        rewrite::{self, PathRewrite},
+    },
     error::FinalError,
     info,
+    integrity::TestPosition,
     list::FileInArchive,
     utils::{self, Bytes, FileVisibilityPolicy},
+    warning,
 };
 
 /// Unpacks the archive given by `archive` into the folder given by `into`.
@@ -23,24 +30,63 @@ use crate::{
 pub fn unpack_archive(
     reader: Box<dyn Read>,
     output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    display_handle: impl Write,
+) -> crate::Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    unpack_entries(archive.entries()?, output_folder, filter, rewrite, display_handle)
+}
+
+/// Unpacks the archive like `unpack_archive`, seeking over the contents of the entries that aren't unpacked
+pub fn unpack_seekable_archive(
+    reader: impl Read + Seek,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    display_handle: impl Write,
+) -> crate::Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    unpack_entries(archive.entries_with_seek()?, output_folder, filter, rewrite, display_handle)
+}
+
+fn unpack_entries<R: Read>(
+    entries: tar::Entries<R>,
+    output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
     mut display_handle: impl Write,
 ) -> crate::Result<Vec<PathBuf>> {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
-    let mut archive = tar::Archive::new(reader);
 
     let mut files_unpacked = vec![];
-    for file in archive.entries()? {
+    for file in entries {
         let mut file = file?;
+        if !filter.is_match(&file.path()?) {
+            continue;
+        }
 
-        let file_path = output_folder.join(file.path()?);
-        file.unpack_in(output_folder)?;
+        let file_path = if rewrite.is_active() {
+            let path = match rewrite.apply(&file.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !unpack_rewritten(&mut file, output_folder, &path, rewrite)? {
+                continue;
+            }
+            output_folder.join(path)
+        } else {
+            let file_path = output_folder.join(file.path()?);
+            file.unpack_in(output_folder)?;
+            file_path
+        };
 
         // This is printed for every file in the archive and has little
         // importance for most users, but would generate lots of
         // spoken text for users using screen readers, braille displays
         // and so on
 
-        info!(@display_handle, inaccessible, "{:?} extracted. ({})", utils::strip_cur_dir(&output_folder.join(file.path()?)), Bytes::new(file.size()));
+        info!(@display_handle, inaccessible, "{:?} extracted. ({})", utils::strip_cur_dir(&file_path), Bytes::new(file.size()));
 
         files_unpacked.push(file_path);
     }
@@ -48,35 +94,128 @@ pub fn unpack_archive(
     Ok(files_unpacked)
 }
 
+/// Unpacks `file` at `path` inside of `output_folder`, with the same checks as `tar::Entry::unpack_in`
+///
+/// Returns `false` if the file was skipped, when it's a hard link to a member whose path was dropped by `rewrite`
+fn unpack_rewritten<R: Read>(
+    file: &mut tar::Entry<R>,
+    output_folder: &Path,
+    path: &Path,
+    rewrite: &PathRewrite,
+) -> crate::Result<bool> {
+    let file_path = output_folder.join(path);
+    rewrite::create_parent_dirs(output_folder, &file_path)?;
+
+    // Hard links refer to the other member by its path in the archive, which is rewritten too
+    if file.header().entry_type().is_hard_link() {
+        let target = match file.link_name()?.and_then(|target| rewrite.apply(&target)) {
+            Some(target) => target,
+            None => return Ok(false),
+        };
+        fs::hard_link(output_folder.join(target), &file_path)?;
+        return Ok(true);
+    }
+
+    file.unpack(&file_path)?;
+    Ok(true)
+}
+
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+pub fn cat_member(reader: Box<dyn Read>, member: &Path, writer: impl Write) -> crate::Result<bool> {
+    let mut archive = tar::Archive::new(reader);
+    write_member(archive.entries()?, member, writer)
+}
+
+/// Writes the contents of `member` like `cat_member`, seeking over the contents of the entries before it
+pub fn cat_seekable_member(reader: impl Read + Seek, member: &Path, writer: impl Write) -> crate::Result<bool> {
+    let mut archive = tar::Archive::new(reader);
+    write_member(archive.entries_with_seek()?, member, writer)
+}
+
+fn write_member<R: Read>(entries: tar::Entries<R>, member: &Path, mut writer: impl Write) -> crate::Result<bool> {
+    for file in entries {
+        let mut file = file?;
+        if !archive::is_member(&file.path()?, member) {
+            continue;
+        }
+
+        let entry_type = file.header().entry_type();
+        if !(entry_type.is_file() || entry_type.is_contiguous() || entry_type.is_gnu_sparse()) {
+            let mut error = archive::not_a_file_error(member);
+            // The contents of hard links are stored with the member they link to
+            if let Some(target) = file.link_name()? {
+                error = error.detail(format!("'{}' is a link to '{}'", member.display(), target.display()));
+            }
+            return Err(error.into());
+        }
+
+        io::copy(&mut file, &mut writer)?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Reads every member of the archive to its end, and then the rest of `reader`
+pub fn test_archive(reader: Box<dyn Read>, position: &mut TestPosition) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for file in archive.entries()? {
+        let file = file?;
+        position.start_member(file.path()?.into_owned());
+        position.read_to_end(file)?;
+    }
+
+    // The checksums of the compression formats are at the end of their data, past the end of the archive
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(())
+}
+
 /// List contents of `archive`, returning a vector of archive entries
 pub fn list_archive(
     mut archive: tar::Archive<impl Read + Send + 'static>,
 ) -> impl Iterator<Item = crate::Result<FileInArchive>> {
-    struct Files(Receiver<crate::Result<FileInArchive>>);
-    impl Iterator for Files {
-        type Item = crate::Result<FileInArchive>;
-
-        fn next(&mut self) -> Option<Self::Item> {
-            self.0.recv().ok()
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for file in archive.entries().expect("entries is only used once") {
+            tx.send(file_in_archive(file)).unwrap();
         }
-    }
+    });
 
+    Files(rx)
+}
+
+/// List contents of `archive` like `list_archive`, seeking over the contents of the entries instead of reading them
+pub fn list_seekable_archive(
+    mut archive: tar::Archive<impl Read + Seek + Send + 'static>,
+) -> impl Iterator<Item = crate::Result<FileInArchive>> {
     let (tx, rx) = mpsc::channel();
     thread::spawn(move || {
-        for file in archive.entries().expect("entries is only used once") {
-            let file_in_archive = (|| {
-                let file = file?;
-                let path = file.path()?.into_owned();
-                let is_dir = file.header().entry_type().is_dir();
-                Ok(FileInArchive { path, is_dir })
-            })();
-            tx.send(file_in_archive).unwrap();
+        for file in archive.entries_with_seek().expect("entries is only used once") {
+            tx.send(file_in_archive(file)).unwrap();
         }
     });
 
     Files(rx)
 }
 
+/// Entries listed by the thread reading the archive
+struct Files(Receiver<crate::Result<FileInArchive>>);
+
+impl Iterator for Files {
+    type Item = crate::Result<FileInArchive>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.recv().ok()
+    }
+}
+
+fn file_in_archive<R: Read>(file: std::io::Result<tar::Entry<R>>) -> crate::Result<FileInArchive> {
+    let file = file?;
+    let path = file.path()?.into_owned();
+    let is_dir = file.header().entry_type().is_dir();
+    Ok(FileInArchive { path, is_dir })
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
 pub fn build_archive_from_paths<W, D>(
     input_filenames: &[PathBuf],
@@ -132,3 +271,67 @@ where
 
     Ok(builder.into_inner()?)
 }
+
+/// Builds a tar archive written to `writer` from the members of the zip archive given by `archive`.
+///
+/// The members are streamed from one archive into the other with their paths, modes and modification times.
+/// `password` is used to decrypt the encrypted members, see [`archive::zip::has_encrypted_files`].
+pub fn build_archive_from_zip<R, W, D>(
+    mut archive: zip::ZipArchive<R>,
+    writer: W,
+    password: Option<&[u8]>,
+    mut display_handle: D,
+) -> crate::Result<W>
+where
+    R: Read + Seek,
+    W: Write,
+    D: Write,
+{
+    let mut builder = tar::Builder::new(writer);
+
+    for idx in 0..archive.len() {
+        let mut file = match password {
+            Some(password) => archive.by_index_decrypt(idx, password)?,
+            None => archive.by_index(idx)?,
+        };
+        let path = match file.enclosed_name() {
+            Some(path) => path,
+            None => {
+                warning!("Skipping '{}', its path leads outside of the archive.", file.name()?);
+                continue;
+            }
+        };
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        info!(@display_handle, inaccessible, "Converting '{}'.", path.display());
+
+        let mut header = tar::Header::new_gnu();
+        // Zip archives made on other systems have no Unix mode
+        let default_mode = if file.is_dir() { 0o755 } else { 0o644 };
+        header.set_mode(file.unix_mode().map_or(default_mode, |mode| mode & 0o7777));
+        let mtime = file.last_modified().and_then(archive::zip::timestamp_from_date_time);
+        header.set_mtime(mtime.and_then(|mtime| mtime.try_into().ok()).unwrap_or(0));
+
+        if file.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            builder.append_data(&mut header, &path, io::empty())?;
+        } else if file.is_symlink() {
+            // The target of symlinks is their contents
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, &path, target)?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size());
+            builder.append_data(&mut header, &path, &mut file)?;
+        }
+    }
+
+    Ok(builder.into_inner()?)
+}
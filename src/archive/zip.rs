@@ -9,23 +9,56 @@ use std::{
 };
 
 use fs_err as fs;
-use zip::{self, read::ZipFile, ZipArchive};
+use zip::{self, read::ZipFile, AesMode, CompressionMethod, ZipArchive};
 
 use crate::{
+    archive::{self, filter::MemberFilter, rewrite::PathRewrite},
     error::FinalError,
+    extension::{self, CompressionFormat},
     info,
+    integrity::TestPosition,
     list::FileInArchive,
+    opts::ZipMethod,
     utils::{
-        self, cd_into_same_dir_as, concatenate_os_str_list, get_invalid_utf8_paths, strip_cur_dir, to_utf, Bytes,
-        FileVisibilityPolicy,
+        self, cd_into_same_dir_as, concatenate_os_str_list, get_invalid_utf8_paths, strip_cur_dir, to_utf,
+        try_infer_extension_from_bytes, Bytes, FileVisibilityPolicy,
     },
+    warning,
 };
 
+/// Size of the buffer used to copy each file into the archive
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Entries this big need ZIP64, as their sizes don't fit in the 32 bits of the regular headers
+const ZIP64_THRESHOLD: u64 = u32::MAX as u64;
+
+/// Whether an entry of this size has to be written with ZIP64 headers
+fn is_large_file(size: u64) -> bool {
+    size >= ZIP64_THRESHOLD
+}
+
+/// How often the progress of a file being compressed is reported, in bytes
+const PROGRESS_REPORT_STEP: u64 = 32 * 1024 * 1024;
+
+/// Extensions of file formats that are already compressed, besides the ones supported by ouch
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "apk", "avi", "avif", "docx", "epub", "flac", "gif", "heic", "jar", "jpeg", "jpg", "m4a", "mkv", "mov", "mp3",
+    "mp4", "odp", "ods", "odt", "ogg", "opus", "png", "pptx", "rar", "webm", "webp", "whl", "woff", "woff2", "xlsx",
+];
+
+/// With `ZipMethod::Auto`, files that deflate to more than this fraction of their size are stored
+const POOR_COMPRESSION_RATIO: f64 = 0.95;
+
 /// Unpacks the archive given by `archive` into the folder given by `output_folder`.
 /// Assumes that output_folder is empty
+///
+/// `password` is used to decrypt the encrypted files, see [`has_encrypted_files`].
 pub fn unpack_archive<R, D>(
     mut archive: ZipArchive<R>,
     output_folder: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    password: Option<&[u8]>,
     mut display_handle: D,
 ) -> crate::Result<Vec<PathBuf>>
 where
@@ -37,17 +70,20 @@ where
     let mut unpacked_files = Vec::with_capacity(archive.len());
 
     for idx in 0..archive.len() {
-        let mut file = archive.by_index(idx)?;
-        let file_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
+        // Both WinZip AES and ZipCrypto are supported, the password is ignored for files that aren't encrypted
+        let mut file = match password {
+            Some(password) => archive.by_index_decrypt(idx, password)?,
+            None => archive.by_index(idx)?,
+        };
+        let file_path = file.enclosed_name().filter(|path| filter.is_match(path));
+        let file_path = match file_path.and_then(|path| rewrite.apply(&path)) {
+            Some(path) => output_folder.join(path),
             None => continue,
         };
 
-        let file_path = output_folder.join(file_path);
-
         check_for_comments(&file);
 
-        match (&*file.name()).ends_with('/') {
+        match file.is_dir() {
             _is_dir @ true => {
                 // This is printed for every file in the archive and has little
                 // importance for most users, but would generate lots of
@@ -59,7 +95,7 @@ where
             _is_file @ false => {
                 if let Some(path) = file_path.parent() {
                     if !path.exists() {
-                        fs::create_dir_all(&path)?;
+                        fs::create_dir_all(path)?;
                     }
                 }
                 let file_path = strip_cur_dir(file_path.as_path());
@@ -67,7 +103,7 @@ where
                 // same reason is in _is_dir: long, often not needed text
                 info!(@display_handle, inaccessible, "{:?} extracted. ({})", file_path.display(), Bytes::new(file.size()));
 
-                let mut output_file = fs::File::create(&file_path)?;
+                let mut output_file = fs::File::create(file_path)?;
                 io::copy(&mut file, &mut output_file)?;
 
                 #[cfg(unix)]
@@ -84,8 +120,86 @@ where
     Ok(unpacked_files)
 }
 
+/// Writes the contents of `member` to `writer`, returns `false` if it isn't in the archive
+///
+/// `password` is used to decrypt the member if it's encrypted, see [`has_encrypted_files`].
+pub fn cat_member<R, W>(
+    mut archive: ZipArchive<R>,
+    member: &Path,
+    password: Option<&[u8]>,
+    mut writer: W,
+) -> crate::Result<bool>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut name = None;
+    for file_name in archive.file_names() {
+        let file_name = file_name?;
+        if archive::is_member(Path::new(file_name.as_ref()), member) {
+            name = Some(file_name.into_owned());
+            break;
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(false),
+    };
+
+    let mut file = match password {
+        Some(password) => archive.by_name_decrypt(&name, password)?,
+        None => archive.by_name(&name)?,
+    };
+    if file.is_dir() || file.is_symlink() {
+        return Err(archive::not_a_file_error(member).into());
+    }
+
+    io::copy(&mut file, &mut writer)?;
+    Ok(true)
+}
+
+/// Reads every member of `archive` to its end, which verifies their CRC32
+///
+/// `password` is used to decrypt the encrypted files, see [`has_encrypted_files`].
+pub fn test_archive<R>(
+    mut archive: ZipArchive<R>,
+    password: Option<&[u8]>,
+    position: &mut TestPosition,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    for idx in 0..archive.len() {
+        let file = match password {
+            Some(password) => archive.by_index_decrypt(idx, password)?,
+            None => archive.by_index(idx)?,
+        };
+        position.start_member(file.name()?.into_owned());
+        position.read_to_end(file)?;
+    }
+    Ok(())
+}
+
+/// Checks if any file of `archive` is encrypted, which requires a password to unpack it
+pub fn has_encrypted_files<R>(archive: &mut ZipArchive<R>) -> crate::Result<bool>
+where
+    R: Read + Seek,
+{
+    for idx in 0..archive.len() {
+        if archive.by_index_raw(idx)?.encrypted() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// List contents of `archive`, returning a vector of archive entries
-pub fn list_archive<R>(mut archive: ZipArchive<R>) -> impl Iterator<Item = crate::Result<FileInArchive>>
+///
+/// The names aren't encrypted, `password` is only checked against the encrypted files so a wrong one is reported.
+pub fn list_archive<R>(
+    mut archive: ZipArchive<R>,
+    password: Option<Vec<u8>>,
+) -> impl Iterator<Item = crate::Result<FileInArchive>>
 where
     R: Read + Seek + Send + 'static,
 {
@@ -102,15 +216,16 @@ where
     thread::spawn(move || {
         for idx in 0..archive.len() {
             let maybe_file_in_archive = (|| {
-                let file = match archive.by_index(idx) {
+                let file = match &password {
+                    Some(password) => archive.by_index_decrypt(idx, password),
+                    None => archive.by_index_raw(idx),
+                };
+                let file = match file {
                     Ok(f) => f,
                     Err(e) => return Some(Err(e.into())),
                 };
 
-                let path = match file.enclosed_name() {
-                    Some(path) => path.to_owned(),
-                    None => return None,
-                };
+                let path = file.enclosed_name()?;
                 let is_dir = file.is_dir();
 
                 Some(Ok(FileInArchive { path, is_dir }))
@@ -124,19 +239,111 @@ where
     Files(rx)
 }
 
+/// Chooses the compression method of a file, `prefix` is the start of its contents
+fn choose_compression_method(zip_method: ZipMethod, path: &Path, prefix: &[u8]) -> CompressionMethod {
+    match zip_method {
+        ZipMethod::Store => CompressionMethod::Stored,
+        ZipMethod::Deflate => CompressionMethod::Deflated,
+        ZipMethod::Bzip2 => CompressionMethod::Bzip2,
+        ZipMethod::Zstd => CompressionMethod::Zstd,
+        ZipMethod::Auto if is_already_compressed(path, prefix) => CompressionMethod::Stored,
+        ZipMethod::Auto => CompressionMethod::Deflated,
+    }
+}
+
+/// Checks the extension, the magic bytes and the compression ratio of the start of the file
+fn is_already_compressed(path: &Path, prefix: &[u8]) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase);
+    if let Some(extension) = extension {
+        if COMPRESSED_EXTENSIONS.contains(&extension.as_str())
+            || extension::to_extension(&extension).is_some_and(|extension| is_compressed(extension.compression_formats))
+        {
+            return true;
+        }
+    }
+
+    // Tar, cpio and ar archives are detected from the magic bytes too, but they aren't compressed
+    let detected = try_infer_extension_from_bytes(prefix);
+    if detected.is_some_and(|extension| is_compressed(extension.compression_formats)) {
+        return true;
+    }
+
+    // Small files are cheap to deflate anyway
+    if prefix.len() < 1024 {
+        return false;
+    }
+    let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::fast());
+    let compressed_len = encoder.write_all(prefix).and_then(|_| encoder.finish()).map_or(0, |output| output.len());
+    compressed_len as f64 > prefix.len() as f64 * POOR_COMPRESSION_RATIO
+}
+
+/// Whether files of these formats are compressed, tar, cpio and ar archives only put the files together
+fn is_compressed(formats: &[CompressionFormat]) -> bool {
+    formats
+        .iter()
+        .any(|format| !matches!(format, CompressionFormat::Tar | CompressionFormat::Cpio | CompressionFormat::Ar))
+}
+
+/// Copies the rest of the file into the zip entry through a bounded buffer, reporting the progress of big files
+///
+/// `written` is the amount of bytes of the file that were already written to the entry
+fn write_file_contents<W, D>(
+    mut file: fs::File,
+    mut written: u64,
+    size: u64,
+    path: &Path,
+    writer: &mut W,
+    mut display_handle: D,
+) -> crate::Result<()>
+where
+    W: Write,
+    D: Write,
+{
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut next_report = PROGRESS_REPORT_STEP;
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(&buffer[..read])?;
+        written += read as u64;
+
+        if written >= next_report {
+            let percentage = written * 100 / size.max(written);
+            info!(@display_handle, inaccessible, "Compressing '{}': {} ({}%).", to_utf(path), Bytes::new(written), percentage);
+            next_report += PROGRESS_REPORT_STEP;
+        }
+    }
+
+    Ok(())
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// The archive is written as a stream, the sizes and checksums of each file are stored in data
+/// descriptors after their contents, so `writer` doesn't need to be seekable.
 pub fn build_archive_from_paths<W, D>(
     input_filenames: &[PathBuf],
     writer: W,
+    compression_level: Option<i32>,
+    zip_method: ZipMethod,
+    password: Option<&str>,
     file_visibility_policy: FileVisibilityPolicy,
     mut display_handle: D,
 ) -> crate::Result<W>
 where
-    W: Write + Seek,
+    W: Write,
     D: Write,
 {
-    let mut writer = zip::ZipWriter::new(writer);
-    let options = zip::write::FileOptions::default();
+    let mut writer = zip::ZipWriter::new_stream(writer);
+    let mut options = zip::write::SimpleFileOptions::default();
+    if let Some(password) = password {
+        options = options.with_aes_encryption(AesMode::Aes256, password);
+    }
 
     // Vec of any filename that failed the UTF-8 check
     let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
@@ -168,9 +375,8 @@ where
             if path.is_dir() {
                 writer.add_directory(path.to_str().unwrap().to_owned(), options)?;
             } else {
-                writer.start_file(path.to_str().unwrap().to_owned(), options)?;
-                let file_bytes = match fs::read(entry.path()) {
-                    Ok(b) => b,
+                let mut file = match fs::File::open(entry.path()) {
+                    Ok(file) => file,
                     Err(e) => {
                         if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
                             // This path is for a broken symlink
@@ -180,18 +386,111 @@ where
                         return Err(e.into());
                     }
                 };
-                writer.write_all(&*file_bytes)?;
+                let size = file.metadata()?.len();
+
+                // The start of the file is used to choose its compression method
+                let mut prefix = Vec::with_capacity(BUFFER_SIZE);
+                (&mut file).take(BUFFER_SIZE as u64).read_to_end(&mut prefix)?;
+                let method = choose_compression_method(zip_method, path, &prefix);
+                // Stored files have no compression level
+                let level = if method == CompressionMethod::Stored { None } else { compression_level };
+
+                // ZIP64 is only used when needed, as some tools can't read it
+                let options = options
+                    .compression_method(method)
+                    .compression_level(level.map(i64::from))
+                    .large_file(is_large_file(size));
+                writer.start_file(path.to_str().unwrap().to_owned(), options)?;
+                writer.write_all(&prefix)?;
+                write_file_contents(file, prefix.len() as u64, size, path, &mut writer, &mut display_handle)?;
             }
         }
 
         env::set_current_dir(previous_location)?;
     }
 
-    let bytes = writer.finish()?;
-    Ok(bytes)
+    let writer = writer.finish()?.into_inner();
+    Ok(writer)
 }
 
-fn check_for_comments(file: &ZipFile) {
+/// Compresses the members of the tar archive given by `archive` into a zip archive written to `writer`.
+///
+/// The members are streamed from one archive into the other with their paths, modes and modification times, which
+/// are rounded to the 2 seconds precision of zip archives. Hard links, device files and fifos are skipped, as zip
+/// archives can't hold them.
+pub fn build_archive_from_tar<R, W, D>(
+    mut archive: tar::Archive<R>,
+    writer: W,
+    compression_level: Option<i32>,
+    zip_method: ZipMethod,
+    mut display_handle: D,
+) -> crate::Result<W>
+where
+    R: Read,
+    W: Write,
+    D: Write,
+{
+    let mut writer = zip::ZipWriter::new_stream(writer);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let name = match path.to_str() {
+            Some(name) => name.to_owned(),
+            None => {
+                let error = FinalError::with_title("Cannot build zip archive")
+                    .detail("Zip archives require files to have valid UTF-8 paths")
+                    .detail(format!("File with an invalid path: {}", to_utf(&path)));
+
+                return Err(error.into());
+            }
+        };
+
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let mut options = zip::write::SimpleFileOptions::default().unix_permissions(header.mode()?);
+        if let Some(date_time) = date_time_from_timestamp(header.mtime()?) {
+            options = options.last_modified_time(date_time);
+        }
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        info!(@display_handle, inaccessible, "Converting '{}'.", name);
+
+        if entry_type.is_dir() {
+            writer.add_directory(name, options)?;
+        } else if entry_type.is_symlink() {
+            let target = entry.link_name()?.unwrap_or_default();
+            writer.add_symlink(name, to_utf(&target), options)?;
+        } else if entry_type.is_file() || entry_type.is_contiguous() || entry_type.is_gnu_sparse() {
+            let size = entry.size();
+
+            // The start of the file is used to choose its compression method
+            let mut prefix = Vec::with_capacity(BUFFER_SIZE);
+            (&mut entry).take(BUFFER_SIZE as u64).read_to_end(&mut prefix)?;
+            let method = choose_compression_method(zip_method, &path, &prefix);
+            // Stored files have no compression level
+            let level = if method == CompressionMethod::Stored { None } else { compression_level };
+
+            let options = options
+                .compression_method(method)
+                .compression_level(level.map(i64::from))
+                .large_file(is_large_file(size));
+            writer.start_file(name, options)?;
+            writer.write_all(&prefix)?;
+            io::copy(&mut entry, &mut writer)?;
+        } else {
+            warning!("Skipping '{}', zip archives can only hold files, directories and symlinks.", name);
+        }
+    }
+
+    let writer = writer.finish()?.into_inner();
+    Ok(writer)
+}
+
+fn check_for_comments<R: Read>(file: &ZipFile<R>) {
     let comment = file.comment();
     if !comment.is_empty() {
         // Zip file comments seem to be pretty rare, but if they are used,
@@ -204,13 +503,12 @@ fn check_for_comments(file: &ZipFile) {
         // the future, maybe asking the user if he wants to display the comment
         // (informing him of its size) would be sensible for both normal and
         // accessibility mode..
-        info!(accessible, "Found comment in {}: {}", file.name(), comment);
+        info!(accessible, "Found comment in {}: {}", file.name().unwrap_or_default(), comment);
     }
 }
 
-#[cfg(unix)]
-/// Attempts to convert a [`zip::DateTime`] to a [`libc::timespec`].
-fn convert_zip_date_time(date_time: zip::DateTime) -> Option<libc::timespec> {
+/// Attempts to convert a [`zip::DateTime`], taken as UTC, to a Unix timestamp.
+pub fn timestamp_from_date_time(date_time: zip::DateTime) -> Option<i64> {
     use time::{Date, Month, PrimitiveDateTime, Time};
 
     // Safety: time::Month is repr(u8) and goes from 1 to 12
@@ -221,21 +519,41 @@ fn convert_zip_date_time(date_time: zip::DateTime) -> Option<libc::timespec> {
     let time = Time::from_hms(date_time.hour(), date_time.minute(), date_time.second()).ok()?;
 
     let date_time = PrimitiveDateTime::new(date, time);
-    let timestamp = date_time.assume_utc().unix_timestamp();
+    Some(date_time.assume_utc().unix_timestamp())
+}
+
+/// Attempts to convert a Unix timestamp to a [`zip::DateTime`] in UTC, which only holds the years 1980 to 2107.
+fn date_time_from_timestamp(timestamp: u64) -> Option<zip::DateTime> {
+    let date_time = time::OffsetDateTime::from_unix_timestamp(timestamp.try_into().ok()?).ok()?;
+
+    zip::DateTime::from_date_and_time(
+        date_time.year().try_into().ok()?,
+        date_time.month() as u8,
+        date_time.day(),
+        date_time.hour(),
+        date_time.minute(),
+        date_time.second(),
+    )
+    .ok()
+}
+
+#[cfg(unix)]
+/// Attempts to convert a [`zip::DateTime`] to a [`libc::timespec`].
+fn convert_zip_date_time(date_time: zip::DateTime) -> Option<libc::timespec> {
+    let timestamp = timestamp_from_date_time(date_time)?;
 
     Some(libc::timespec { tv_sec: timestamp, tv_nsec: 0 })
 }
 
 #[cfg(unix)]
-fn set_last_modified_time(file: &fs::File, zip_file: &ZipFile) -> crate::Result<()> {
+fn set_last_modified_time<R: Read>(file: &fs::File, zip_file: &ZipFile<R>) -> crate::Result<()> {
     use std::os::unix::prelude::AsRawFd;
 
     use libc::UTIME_NOW;
 
     let now = libc::timespec { tv_sec: 0, tv_nsec: UTIME_NOW };
 
-    let last_modified = zip_file.last_modified();
-    let last_modified = convert_zip_date_time(last_modified).unwrap_or(now);
+    let last_modified = zip_file.last_modified().and_then(convert_zip_date_time).unwrap_or(now);
 
     // The first value is the last accessed time, which we'll set as being right now.
     // The second value is the last modified time, which we'll copy over from the zip archive
@@ -250,7 +568,7 @@ fn set_last_modified_time(file: &fs::File, zip_file: &ZipFile) -> crate::Result<
 }
 
 #[cfg(unix)]
-fn __unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+fn __unix_set_permissions<R: Read>(file_path: &Path, file: &ZipFile<R>) -> crate::Result<()> {
     use std::{fs::Permissions, os::unix::fs::PermissionsExt};
 
     if let Some(mode) = file.unix_mode() {
@@ -259,3 +577,33 @@ fn __unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()>
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_large_file() {
+        assert!(!is_large_file(0));
+        assert!(!is_large_file(u64::from(u32::MAX) - 1));
+        assert!(is_large_file(u64::from(u32::MAX)));
+        assert!(is_large_file(u64::from(u32::MAX) + 1));
+    }
+
+    #[test]
+    fn test_write_file_contents_reports_progress() {
+        crate::cli::ACCESSIBLE.get_or_init(|| false);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let size = PROGRESS_REPORT_STEP + PROGRESS_REPORT_STEP / 2;
+        file.as_file().set_len(size).unwrap();
+
+        let mut messages = vec![];
+        let reader = fs::File::open(file.path()).unwrap();
+        write_file_contents(reader, 0, size, Path::new("file"), &mut io::sink(), &mut messages).unwrap();
+
+        let messages = String::from_utf8(messages).unwrap();
+        assert_eq!(messages.lines().count(), 1);
+        assert!(messages.contains("Compressing 'file': 33.55 MB (66%)."), "{messages}");
+    }
+}
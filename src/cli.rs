@@ -10,28 +10,69 @@ use clap::Parser;
 use fs_err as fs;
 use once_cell::sync::OnceCell;
 
-use crate::{utils::FileVisibilityPolicy, Opts, QuestionPolicy, Subcommand};
+use crate::{
+    error::FinalError,
+    utils::{self, FileVisibilityPolicy},
+    Opts, QuestionPolicy, Subcommand,
+};
 
 /// Whether to enable accessible output (removes info output and reduces other
 /// output, removes visual markers like '[' and ']').
 /// Removes th progress bar as well
 pub static ACCESSIBLE: OnceCell<bool> = OnceCell::new();
 
+/// Whether stdout is used to output data, when `-` is given as the output path.
+/// In this case, messages are written to stderr instead.
+pub static STDOUT_IS_DATA: OnceCell<bool> = OnceCell::new();
+
+/// Whether stdin is used to input data, when `-` is given as an input path.
+/// In this case, questions can't be asked to the user.
+pub static STDIN_IS_DATA: OnceCell<bool> = OnceCell::new();
+
 impl Opts {
     /// A helper method that calls `clap::Parser::parse`.
     ///
     /// And:
     ///   1. Make paths absolute.
     ///   2. Checks the QuestionPolicy.
+    ///   3. Checks if stdin or stdout are used for data.
     pub fn parse_args() -> crate::Result<(Self, QuestionPolicy, FileVisibilityPolicy)> {
         let mut opts = Self::parse();
 
         ACCESSIBLE.set(opts.accessible).unwrap();
 
-        let (Subcommand::Compress { files, .. }
-        | Subcommand::Decompress { files, .. }
-        | Subcommand::List { archives: files, .. }) = &mut opts.cmd;
-        *files = canonicalize_files(files)?;
+        let files = match &mut opts.cmd {
+            Subcommand::Compress { files, .. }
+            | Subcommand::Decompress { files, .. }
+            | Subcommand::List { archives: files, .. }
+            | Subcommand::Test { archives: files, .. }
+            | Subcommand::ZstdTrain { samples: files, .. } => {
+                *files = canonicalize_files(files)?;
+                files.clone()
+            }
+            Subcommand::Cat { archive: file, .. } | Subcommand::Convert { input: file, .. } => {
+                *file = canonicalize_files(&[&file])?.remove(0);
+                vec![file.clone()]
+            }
+        };
+
+        let stdin_count = files.iter().filter(|file| utils::is_stdio(file)).count();
+        if stdin_count > 1 {
+            let error = FinalError::with_title("Cannot read stdin more than once")
+                .detail(format!("'-' was given {} times as an input", stdin_count));
+            return Err(error.into());
+        }
+        STDIN_IS_DATA.set(stdin_count == 1).unwrap();
+
+        // Single file formats decompressed from stdin are written to stdout
+        let stdout_is_data = match &opts.cmd {
+            Subcommand::Compress { output, .. } => utils::is_stdio(output),
+            Subcommand::Decompress { .. } => stdin_count == 1,
+            Subcommand::Cat { .. } => true,
+            Subcommand::Convert { output, .. } => utils::is_stdio(output),
+            Subcommand::List { .. } | Subcommand::Test { .. } | Subcommand::ZstdTrain { .. } => false,
+        };
+        STDOUT_IS_DATA.set(stdout_is_data).unwrap();
 
         let skip_questions_positively = match (opts.yes, opts.no) {
             (false, false) => QuestionPolicy::Ask,
@@ -52,5 +93,16 @@ impl Opts {
 }
 
 fn canonicalize_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
-    files.iter().map(fs::canonicalize).collect()
+    files
+        .iter()
+        .map(|file| {
+            let file = file.as_ref();
+            // `-` stands for stdin, and is kept as it is
+            if utils::is_stdio(file) {
+                Ok(file.to_path_buf())
+            } else {
+                fs::canonicalize(file)
+            }
+        })
+        .collect()
 }
@@ -3,16 +3,18 @@
 //! Also, where correctly call functions based on the detected `Command`.
 
 use std::{
-    io::{self, BufReader, BufWriter, Read, Write},
-    ops::ControlFlow,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    num::{NonZeroU64, NonZeroUsize},
+    ops::{ControlFlow, RangeInclusive},
     path::{Path, PathBuf},
+    thread,
 };
 
 use fs_err as fs;
 use utils::colors;
 
 use crate::{
-    archive,
+    archive::{self, filter::MemberFilter, rewrite::PathRewrite},
     error::FinalError,
     extension::{
         self,
@@ -20,25 +22,70 @@ use crate::{
         Extension,
     },
     info,
+    integrity::TestPosition,
+    level::{self, CompressionLevels, Level, BROTLI_DEFAULT_QUALITY},
     list::{self, FileInArchive, ListOptions},
+    lzw::{LzwDecoder, LzwEncoder},
+    opts::ZipMethod,
+    parallel_gzip::ParallelGzEncoder,
     progress::Progress,
     utils::{
-        self, concatenate_os_str_list, dir_is_empty, nice_directory_display, to_utf, try_infer_extension,
-        user_wants_to_continue, FileVisibilityPolicy,
+        self, concatenate_os_str_list, dir_is_empty, is_stdio, message_output, nice_directory_display, to_utf,
+        try_infer_extension, try_infer_extension_from_bytes, user_wants_to_continue, FileVisibilityPolicy,
+        MAGIC_BYTES_LEN,
     },
-    warning, Opts, QuestionAction, QuestionPolicy, Subcommand,
+    warning,
+    zstd_seekable::{SeekableZstdDecoder, SeekableZstdEncoder},
+    Opts, QuestionAction, QuestionPolicy, Subcommand,
 };
 
-// Message used to advice the user that .zip archives have limitations that require it to load everything into memory at once
-// and this can lead to out-of-memory scenarios for archives that are big enough.
-const ZIP_IN_MEMORY_LIMITATION_WARNING: &str =
-    "\tThere is a limitation for .zip archives with extra extensions. (e.g. <file>.zip.gz)
-\tThe design of .zip makes it impossible to compress via stream, so it must be done entirely in memory.
-\tBy compressing .zip with extra compression formats, you can run out of RAM if the file is too large!";
-
 // Used in BufReader and BufWriter to perform less syscalls
 const BUFFER_CAPACITY: usize = 1024 * 64;
 
+/// Range of the window logs supported by zstd, the windows of its frames are 2^log bytes long
+const ZSTD_WINDOW_LOG_RANGE: RangeInclusive<u32> = 10..=if cfg!(target_pointer_width = "32") { 30 } else { 31 };
+
+/// Options controlling how the compression formats encode the data
+#[derive(Debug, Clone)]
+pub struct CompressionOptions {
+    /// Compression level of each format
+    pub levels: CompressionLevels,
+    /// Number of threads used by the formats that support multithreading
+    pub threads: usize,
+    /// Compression method of the files inside of zip archives
+    pub zip_method: ZipMethod,
+    /// Password used to encrypt the files inside of zip archives
+    pub password: Option<String>,
+    /// Dictionary used by zstd
+    pub zstd_dict: Option<Vec<u8>>,
+    /// Window log of zstd's long distance matching, which is disabled if `None`
+    pub zstd_long: Option<u32>,
+    /// Whether zstd compresses into its seekable format
+    pub zstd_seekable: bool,
+}
+
+/// Options controlling how the compression formats decode the data
+#[derive(Debug, Clone, Default)]
+pub struct DecompressionOptions {
+    /// Dictionary used by zstd
+    pub zstd_dict: Option<Vec<u8>>,
+}
+
+/// LZMA-alone encoder, which finishes its stream when dropped like `xz2::write::XzEncoder`
+///
+/// The format can't be flushed in the middle of the stream, so flushing only flushes the writer below it.
+struct LzmaAloneEncoder<W: Write>(xz2::write::XzEncoder<W>);
+
+impl<W: Write> Write for LzmaAloneEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.get_mut().flush()
+    }
+}
+
 fn represents_several_files(files: &[PathBuf]) -> bool {
     let is_non_empty_dir = |path: &PathBuf| {
         let is_non_empty = || !dir_is_empty(path);
@@ -56,9 +103,25 @@ pub fn run(
     file_visibility_policy: FileVisibilityPolicy,
 ) -> crate::Result<()> {
     match args.cmd {
-        Subcommand::Compress { mut files, output: output_path } => {
+        Subcommand::Compress {
+            mut files,
+            output: output_path,
+            format,
+            level,
+            fast,
+            best,
+            threads,
+            zip_method,
+            password,
+            zstd_dict,
+            zstd_long,
+            zstd_seekable,
+        } => {
+            // `-` as the output means that the data should be written to stdout
+            let output_is_stdout = is_stdio(&output_path);
+
             // If the output_path file exists and is the same as some of the input files, warn the user and skip those inputs (in order to avoid compression recursion)
-            if output_path.exists() {
+            if !output_is_stdout && output_path.exists() {
                 clean_input_files_if_needed(&mut files, &fs::canonicalize(&output_path)?);
             }
             // After cleaning, if there are no input files left, exit
@@ -66,8 +129,20 @@ pub fn run(
                 return Err(FinalError::with_title("No files to compress").into());
             }
 
-            // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma]
-            let mut formats = extension::extensions_from_path(&output_path);
+            // Formats from the --format flag, or from the path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Xz]
+            let mut formats = match &format {
+                Some(format) => extension::parse_format(format)?,
+                None => extension::extensions_from_path(&output_path),
+            };
+
+            if formats.is_empty() && output_is_stdout {
+                let error = FinalError::with_title("Cannot compress to stdout.")
+                    .detail("The compression format can't be detected from '-', as it has no extension")
+                    .hint("Use the '--format' flag to choose the format:")
+                    .hint("  ouch compress <FILES>... - --format tar.gz");
+
+                return Err(error.into());
+            }
 
             if formats.is_empty() {
                 let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
@@ -82,29 +157,41 @@ pub fn run(
                 return Err(error.into());
             }
 
-            if !formats.get(0).map(Extension::is_archive).unwrap_or(false) && represents_several_files(&files) {
-                // This piece of code creates a suggestion for compressing multiple files
-                // It says:
-                // Change from file.bz.xz
-                // To          file.tar.bz.xz
-                let extensions_text: String = formats.iter().map(|format| format.to_string()).collect();
-
+            if !formats.first().map(Extension::is_archive).unwrap_or(false) && represents_several_files(&files) {
                 let output_path = to_utf(&output_path).to_string();
 
-                // Breaks if Lzma is .lz or .lzma and not .xz
-                // Or if Bzip is .bz2 and not .bz
-                let extensions_start_position = output_path.rfind(&extensions_text).unwrap();
-                let pos = extensions_start_position - 1;
-                let mut suggested_output_path = output_path.to_string();
-                suggested_output_path.insert_str(pos, ".tar");
-
                 let error = FinalError::with_title(format!("Cannot compress to '{}'.", output_path))
                     .detail("You are trying to compress multiple files.")
-                    .detail(format!("The compression format '{}' cannot receive multiple files.", &formats[0]))
-                    .detail("The only supported formats that archive files into an archive are .tar and .zip.")
-                    .hint(format!("Try inserting '.tar' or '.zip' before '{}'.", &formats[0]))
-                    .hint(format!("From: {}", output_path))
-                    .hint(format!("To:   {}", suggested_output_path));
+                    .detail(format!("The compression format '{}' cannot receive multiple files.", formats[0]))
+                    .detail("The only supported formats that archive files into an archive are .tar, .zip, .7z, .cpio and .ar.");
+
+                let error = if let Some(format) = &format {
+                    // The formats came from the --format flag, suggest changing it instead of the path
+                    let format = format.to_string_lossy();
+                    let format = format.strip_prefix('.').unwrap_or(&format);
+
+                    error
+                        .hint(format!("Try inserting 'tar.' or 'zip.' before '{}'.", format))
+                        .hint(format!("From: --format {}", format))
+                        .hint(format!("To:   --format tar.{}", format))
+                } else {
+                    // This piece of code creates a suggestion for compressing multiple files
+                    // It says:
+                    // Change from file.bz.xz
+                    // To          file.tar.bz.xz
+                    let extensions_text: String = formats.iter().map(|format| format.to_string()).collect();
+
+                    // Breaks if Bzip is .bz2 and not .bz
+                    let extensions_start_position = output_path.rfind(&extensions_text).unwrap();
+                    let pos = extensions_start_position - 1;
+                    let mut suggested_output_path = output_path.to_string();
+                    suggested_output_path.insert_str(pos, ".tar");
+
+                    error
+                        .hint(format!("Try inserting '.tar' or '.zip' before '{}'.", formats[0]))
+                        .hint(format!("From: {}", output_path))
+                        .hint(format!("To:   {}", suggested_output_path))
+                };
 
                 return Err(error.into());
             }
@@ -119,12 +206,95 @@ pub fn run(
                 return Err(error.into());
             }
 
-            if output_path.exists() && !utils::user_wants_to_overwrite(&output_path, question_policy)? {
+            if formats[0].compression_formats[0] == Rar {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("RAR archives can only be decompressed, creating them isn't supported")
+                    .hint("Try compressing into a .zip or .7z archive instead");
+
+                return Err(error.into());
+            }
+
+            let levels = CompressionLevels::new(level.as_deref(), fast, best)?;
+            levels.validate(&formats)?;
+
+            let threads = match threads {
+                Some(threads) => threads.get(),
+                None => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            };
+            if password.is_some() && formats[0].compression_formats[0] != Zip {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("Only zip archives can be encrypted with a password")
+                    .hint("Try compressing into a .zip archive instead");
+
+                return Err(error.into());
+            }
+
+            let has_zstd = formats.iter().flat_map(Extension::iter).any(|format| *format == Zstd);
+            if zstd_dict.is_some() && !has_zstd {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("Only zstd can compress with a dictionary")
+                    .hint("Try compressing into a .zst file instead");
+
+                return Err(error.into());
+            }
+            let zstd_dict = zstd_dict.map(fs::read).transpose()?;
+
+            if zstd_long.is_some() && !has_zstd {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("Only zstd has long distance matching")
+                    .hint("Try compressing into a .zst file instead");
+
+                return Err(error.into());
+            }
+            if let Some(window_log) = zstd_long.filter(|log| !ZSTD_WINDOW_LOG_RANGE.contains(log)) {
+                let error =
+                    FinalError::with_title(format!("Invalid zstd window log '{}'", window_log)).detail(format!(
+                        "The window log must be between {} and {}",
+                        ZSTD_WINDOW_LOG_RANGE.start(),
+                        ZSTD_WINDOW_LOG_RANGE.end()
+                    ));
+
+                return Err(error.into());
+            }
+            if zstd_seekable && !has_zstd {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail("Only zstd has a seekable format")
+                    .hint("Try compressing into a .tar.zst archive instead");
+
+                return Err(error.into());
+            }
+
+            let options =
+                CompressionOptions { levels, threads, zip_method, password, zstd_dict, zstd_long, zstd_seekable };
+
+            if files.iter().any(|file| is_stdio(file)) && formats[0].is_archive() {
+                let error = FinalError::with_title(format!("Cannot compress to '{}'.", to_utf(&output_path)))
+                    .detail(format!("Cannot build a '{}' archive from the data read from stdin.", formats[0]))
+                    .detail("Archives are built from files and directories, and stdin has no name or metadata.")
+                    .hint("If stdin already carries an archive, leave the archive format out:")
+                    .hint("  tar c <FILES>... | ouch compress - output.gz");
+
+                return Err(error.into());
+            }
+
+            if output_is_stdout && atty::is(atty::Stream::Stdout) {
+                let error = FinalError::with_title("Cannot compress to stdout.")
+                    .detail("Refusing to write compressed data to a terminal")
+                    .hint("Redirect the output to a file or to another command:")
+                    .hint("  ouch compress <FILES>... - --format gz > output.gz");
+
+                return Err(error.into());
+            }
+
+            if !output_is_stdout
+                && output_path.exists()
+                && !utils::user_wants_to_overwrite(&output_path, question_policy)?
+            {
                 // User does not want to overwrite this file, skip and return without any errors
                 return Ok(());
             }
 
-            let output_file = fs::File::create(&output_path)?;
+            let output_file = if output_is_stdout { None } else { Some(fs::File::create(&output_path)?) };
 
             if !represents_several_files(&files) {
                 // It is possible the file is already partially compressed so we don't want to compress it again
@@ -167,17 +337,16 @@ pub fn run(
                     formats = new_formats;
                 }
             }
-            let compress_result =
-                compress_files(files, formats, output_file, &output_path, question_policy, file_visibility_policy);
+            let compress_result = compress_files(files, formats, output_file, &options, file_visibility_policy);
 
-            if let Ok(true) = compress_result {
+            if compress_result.is_ok() {
                 // this is only printed once, so it doesn't result in much text. On the other hand,
                 // having a final status message is important especially in an accessibility context
                 // as screen readers may not read a commands exit code, making it hard to reason
                 // about whether the command succeeded without such a message
                 info!(accessible, "Successfully compressed '{}'.", to_utf(&output_path));
-            } else {
-                // If Ok(false) or Err() occurred, delete incomplete file
+            } else if !output_is_stdout {
+                // If Err() occurred, delete incomplete file
                 // Print an extra alert message pointing out that we left a possibly
                 // CORRUPTED FILE at `output_path`
                 if let Err(err) = fs::remove_file(&output_path) {
@@ -190,24 +359,54 @@ pub fn run(
 
             compress_result?;
         }
-        Subcommand::Decompress { files, output_dir } => {
+        Subcommand::Decompress {
+            files,
+            output_dir,
+            format,
+            password,
+            zstd_dict,
+            members,
+            exclude,
+            strip_components,
+            transform,
+        } => {
+            let options = DecompressionOptions { zstd_dict: zstd_dict.map(fs::read).transpose()? };
+            let filter = MemberFilter::new(&members, &exclude)?;
+            let rewrite = PathRewrite::new(strip_components, transform.as_deref())?;
+
             let mut output_paths = vec![];
             let mut formats = vec![];
 
-            for path in files.iter() {
-                let (file_output_path, file_formats) = extension::separate_known_extensions_from_name(path);
-                output_paths.push(file_output_path);
-                formats.push(file_formats);
-            }
+            if let Some(format) = format {
+                let format = extension::parse_format(&format)?;
 
-            if let ControlFlow::Break(_) = check_mime_type(&files, &mut formats, question_policy)? {
-                return Ok(());
+                for path in files.iter() {
+                    // Known extensions are still removed from the output name, "file.tar.gz" -> "file"
+                    // But an opaque name, "build.bin", is only stripped of its last extension -> "build"
+                    let (mut file_output_path, _) = extension::separate_known_extensions_from_name(path);
+                    if file_output_path == path {
+                        file_output_path = path.file_stem().map(Path::new).unwrap_or(file_output_path);
+                    }
+                    output_paths.push(file_output_path);
+                    formats.push(format.clone());
+                }
+            } else {
+                for path in files.iter() {
+                    let (file_output_path, file_formats) = extension::separate_known_extensions_from_name(path);
+                    output_paths.push(file_output_path);
+                    formats.push(file_formats);
+                }
+
+                if let ControlFlow::Break(_) = check_mime_type(&files, &mut formats, question_policy)? {
+                    return Ok(());
+                }
             }
 
+            // The formats of stdin are detected when it's read
             let files_missing_format: Vec<PathBuf> = files
                 .iter()
                 .zip(&formats)
-                .filter(|(_, formats)| formats.is_empty())
+                .filter(|(input_path, formats)| formats.is_empty() && !is_stdio(input_path))
                 .map(|(input_path, _)| PathBuf::from(input_path))
                 .collect();
 
@@ -241,26 +440,49 @@ pub fn run(
             };
 
             for ((input_path, formats), file_name) in files.iter().zip(formats).zip(output_paths) {
+                // Archives read from stdin have no name, it's only used if they need a folder of their own
+                let file_name = if is_stdio(input_path) { Path::new("stdin") } else { file_name };
                 let output_file_path = output_dir.join(file_name); // Path used by single file format archives
-                decompress_file(input_path, formats, &output_dir, output_file_path, question_policy)?;
+                decompress_file(
+                    input_path,
+                    formats,
+                    &output_dir,
+                    output_file_path,
+                    password.as_deref(),
+                    // Each archive must have the members given
+                    &filter.clone(),
+                    &rewrite,
+                    &options,
+                    question_policy,
+                )?;
             }
         }
-        Subcommand::List { archives: files, tree } => {
-            let mut formats = vec![];
+        Subcommand::List { archives: files, tree, format, password, zstd_dict } => {
+            let options = DecompressionOptions { zstd_dict: zstd_dict.map(fs::read).transpose()? };
 
-            for path in files.iter() {
-                let (_, file_formats) = extension::separate_known_extensions_from_name(path);
-                formats.push(file_formats);
-            }
+            let formats = if let Some(format) = format {
+                let format = extension::parse_format(&format)?;
+                vec![format; files.len()]
+            } else {
+                let mut formats = vec![];
 
-            if let ControlFlow::Break(_) = check_mime_type(&files, &mut formats, question_policy)? {
-                return Ok(());
-            }
+                for path in files.iter() {
+                    let (_, file_formats) = extension::separate_known_extensions_from_name(path);
+                    formats.push(file_formats);
+                }
+
+                if let ControlFlow::Break(_) = check_mime_type(&files, &mut formats, question_policy)? {
+                    return Ok(());
+                }
+                formats
+            };
 
+            // The formats of stdin are detected when it's read
             let not_archives: Vec<PathBuf> = files
                 .iter()
                 .zip(&formats)
-                .filter(|(_, formats)| !formats.get(0).map(Extension::is_archive).unwrap_or(false))
+                .filter(|(path, formats)| !(formats.is_empty() && is_stdio(path)))
+                .filter(|(_, formats)| !formats.first().map(Extension::is_archive).unwrap_or(false))
                 .map(|(path, _)| path.clone())
                 .collect();
 
@@ -279,8 +501,195 @@ pub fn run(
                     println!();
                 }
                 let formats = formats.iter().flat_map(Extension::iter).map(Clone::clone).collect();
-                list_archive_contents(archive_path, formats, list_options, question_policy)?;
+                list_archive_contents(archive_path, formats, list_options, password.as_deref(), &options)?;
+            }
+        }
+        Subcommand::Cat { archive, member, format, password, zstd_dict } => {
+            let options = DecompressionOptions { zstd_dict: zstd_dict.map(fs::read).transpose()? };
+
+            let mut formats = match format {
+                Some(format) => vec![extension::parse_format(&format)?],
+                None => vec![extension::separate_known_extensions_from_name(&archive).1],
+            };
+            if let ControlFlow::Break(_) =
+                check_mime_type(std::slice::from_ref(&archive), &mut formats, question_policy)?
+            {
+                return Ok(());
+            }
+
+            // The formats of stdin are detected when it's read
+            let formats = formats.remove(0);
+            if formats.is_empty() && !is_stdio(&archive) {
+                let error = FinalError::with_title(format!("Cannot write '{}' to stdout", to_utf(&archive)))
+                    .detail("Its format can't be detected from its extension")
+                    .hint("Choose the format with the '--format' flag:")
+                    .hint(format!("  ouch cat {} --format tar.gz", to_utf(&archive)));
+
+                return Err(error.into());
+            }
+
+            let formats = formats.iter().flat_map(Extension::iter).copied().collect();
+            cat(&archive, formats, member.as_deref(), password.as_deref(), &options)?;
+        }
+        Subcommand::Test { archives: files, format, password, zstd_dict } => {
+            let options = DecompressionOptions { zstd_dict: zstd_dict.map(fs::read).transpose()? };
+
+            let formats = if let Some(format) = format {
+                let format = extension::parse_format(&format)?;
+                vec![format; files.len()]
+            } else {
+                let mut formats = vec![];
+
+                for path in files.iter() {
+                    let (_, file_formats) = extension::separate_known_extensions_from_name(path);
+                    formats.push(file_formats);
+                }
+
+                if let ControlFlow::Break(_) = check_mime_type(&files, &mut formats, question_policy)? {
+                    return Ok(());
+                }
+                formats
+            };
+
+            // Every file is tested, even after one of them fails
+            let mut failures = 0;
+            for (archive_path, formats) in files.iter().zip(formats) {
+                let formats = formats.iter().flat_map(Extension::iter).copied().collect();
+                let mut position = TestPosition::default();
+
+                match test_archive(archive_path, formats, password.as_deref(), &options, &mut position) {
+                    Ok(()) => println!("OK    {}", to_utf(archive_path)),
+                    Err(err) => {
+                        failures += 1;
+                        if position.has_started() {
+                            println!("FAIL  {}: corrupt {}", to_utf(archive_path), position);
+                        } else {
+                            println!("FAIL  {}", to_utf(archive_path));
+                        }
+                        eprintln!("{}", err);
+                    }
+                }
+            }
+
+            if failures > 0 {
+                let error = FinalError::with_title(format!("{} of {} files failed the test", failures, files.len()));
+                return Err(error.into());
+            }
+        }
+        Subcommand::Convert {
+            input,
+            output: output_path,
+            format,
+            input_format,
+            level,
+            fast,
+            best,
+            threads,
+            zip_method,
+            password,
+        } => {
+            // `-` as the output means that the data should be written to stdout
+            let output_is_stdout = is_stdio(&output_path);
+
+            let output_formats = match &format {
+                Some(format) => extension::parse_format(format)?,
+                None => extension::extensions_from_path(&output_path),
+            };
+            if output_formats.is_empty() {
+                let error = FinalError::with_title(format!("Cannot convert to '{}'.", to_utf(&output_path)))
+                    .detail("The formats to convert to are detected from the extensions of the output")
+                    .hint("Try adding supported extensions (see --help):")
+                    .hint(format!("  ouch convert {} {}.tar.zst", to_utf(&input), to_utf(&output_path)))
+                    .hint("")
+                    .hint("Alternatively, you can overwrite this option by using the '--format' flag:")
+                    .hint(format!("  ouch convert {} {} --format tar.zst", to_utf(&input), to_utf(&output_path)));
+
+                return Err(error.into());
+            }
+            if let Some(format) = output_formats.iter().skip(1).find(|format| format.is_archive()) {
+                let error = FinalError::with_title(format!("Cannot convert to '{}'.", to_utf(&output_path)))
+                    .detail(format!("Found the format '{}' in an incorrect position.", format))
+                    .detail(format!("'{}' can only be used at the start of the file extension.", format));
+
+                return Err(error.into());
+            }
+
+            let levels = CompressionLevels::new(level.as_deref(), fast, best)?;
+            levels.validate(&output_formats)?;
+            let threads = match threads {
+                Some(threads) => threads.get(),
+                None => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            };
+            let options = CompressionOptions {
+                levels,
+                threads,
+                zip_method,
+                password: None,
+                zstd_dict: None,
+                zstd_long: None,
+                zstd_seekable: false,
+            };
+
+            let mut input_formats = match input_format {
+                Some(format) => vec![extension::parse_format(&format)?],
+                None => vec![extension::separate_known_extensions_from_name(&input).1],
+            };
+            if let ControlFlow::Break(_) =
+                check_mime_type(std::slice::from_ref(&input), &mut input_formats, question_policy)?
+            {
+                return Ok(());
+            }
+            // The formats of stdin are detected when it's read
+            let input_formats = input_formats.remove(0);
+            if input_formats.is_empty() && !is_stdio(&input) {
+                let error = FinalError::with_title(format!("Cannot convert '{}'.", to_utf(&input)))
+                    .detail("Its format can't be detected from its extension")
+                    .hint("Choose the format with the '--input-format' flag:")
+                    .hint(format!("  ouch convert {} {} --input-format tar.gz", to_utf(&input), to_utf(&output_path)));
+
+                return Err(error.into());
+            }
+
+            if output_is_stdout && atty::is(atty::Stream::Stdout) {
+                let error = FinalError::with_title("Cannot convert to stdout.")
+                    .detail("Refusing to write compressed data to a terminal")
+                    .hint("Redirect the output to a file or to another command:")
+                    .hint("  ouch convert <INPUT> - --format tar.zst > output.tar.zst");
+
+                return Err(error.into());
+            }
+            if !output_is_stdout && output_path.exists() {
+                if fs::canonicalize(&output_path)? == input {
+                    let error = FinalError::with_title(format!("Cannot convert '{}'.", to_utf(&input)))
+                        .detail("The output is the same file as the input");
+
+                    return Err(error.into());
+                }
+                if !utils::user_wants_to_overwrite(&output_path, question_policy)? {
+                    // User does not want to overwrite this file, skip and return without any errors
+                    return Ok(());
+                }
+            }
+
+            let output_file = if output_is_stdout { None } else { Some(fs::File::create(&output_path)?) };
+            let input_formats = input_formats.iter().flat_map(Extension::iter).copied().collect();
+            let output_formats = output_formats.iter().flat_map(Extension::iter).copied().collect();
+            let convert_result =
+                convert_file(&input, input_formats, output_file, output_formats, password.as_deref(), &options);
+
+            if convert_result.is_ok() {
+                info!(accessible, "Successfully converted '{}' into '{}'.", to_utf(&input), to_utf(&output_path));
+            } else if !output_is_stdout {
+                // The incomplete output is deleted, so it isn't taken for a complete one
+                if let Err(err) = fs::remove_file(&output_path) {
+                    warning!("Could not delete the incomplete '{}': {}", to_utf(&output_path), err);
+                }
             }
+
+            convert_result?;
+        }
+        Subcommand::ZstdTrain { samples, output, max_size } => {
+            train_zstd_dict(&samples, &output, max_size, file_visibility_policy, question_policy)?;
         }
     }
     Ok(())
@@ -288,167 +697,230 @@ pub fn run(
 
 // Compress files into an `output_file`
 //
-// files are the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"]
+// files are the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"], or ["-"] for stdin
 // formats contains each format necessary for compression, example: [Tar, Gz] (in compression order)
-// output_file is the resulting compressed file name, example: "compressed.tar.gz"
+// output_file is the resulting compressed file, example: "compressed.tar.gz", or None for stdout
 //
 // Returns Ok(true) if compressed all files successfully, and Ok(false) if user opted to skip files
 fn compress_files(
     files: Vec<PathBuf>,
     formats: Vec<Extension>,
-    output_file: fs::File,
-    output_dir: &Path,
-    question_policy: QuestionPolicy,
+    output_file: Option<fs::File>,
+    options: &CompressionOptions,
     file_visibility_policy: FileVisibilityPolicy,
-) -> crate::Result<bool> {
+) -> crate::Result<()> {
     // The next lines are for displaying the progress bar
     // If the input files contain a directory, then the total size will be underestimated
+    // If the input is stdin, its size is unknown
     let (total_input_size, precise) = files
         .iter()
-        .map(|f| (f.metadata().expect("file exists").len(), f.is_file()))
+        .map(|f| if is_stdio(f) { (0, false) } else { (f.metadata().expect("file exists").len(), f.is_file()) })
         .fold((0, true), |(total_size, and_precise), (size, precise)| (total_size + size, and_precise & precise));
 
     // NOTE: canonicalize is here to avoid a weird bug:
     //      > If output_file_path is a nested path and it exists and the user overwrite it
     //      >> output_file_path.exists() will always return false (somehow)
     //      - canonicalize seems to fix this
-    let output_file_path = output_file.path().canonicalize()?;
-
-    let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
-
-    let mut writer: Box<dyn Write> = Box::new(file_writer);
-
-    // Grab previous encoder and wrap it inside of a new one
-    let chain_writer_encoder = |format: &CompressionFormat, encoder: Box<dyn Write>| -> crate::Result<Box<dyn Write>> {
-        let encoder: Box<dyn Write> = match format {
-            Gzip => Box::new(flate2::write::GzEncoder::new(encoder, Default::default())),
-            Bzip => Box::new(bzip2::write::BzEncoder::new(encoder, Default::default())),
-            Lz4 => Box::new(lzzzz::lz4f::WriteCompressor::new(encoder, Default::default())?),
-            Lzma => Box::new(xz2::write::XzEncoder::new(encoder, 6)),
-            Snappy => Box::new(snap::write::FrameEncoder::new(encoder)),
-            Zstd => {
-                let zstd_encoder = zstd::stream::write::Encoder::new(encoder, Default::default());
-                // Safety:
-                //     Encoder::new() can only fail if `level` is invalid, but Default::default()
-                //     is guaranteed to be valid
-                Box::new(zstd_encoder.unwrap().auto_finish())
-            }
-            Tar | Zip => unreachable!(),
-        };
-        Ok(encoder)
+    let output_file_path = output_file.as_ref().map(|file| file.path().canonicalize()).transpose()?;
+
+    // The progress is checked through the size of the output file, which stdout doesn't have
+    let current_position_fn = || -> Option<Box<dyn Fn() -> u64 + Send>> {
+        let output_file_path = output_file_path.clone()?;
+        Some(Box::new(move || output_file_path.metadata().expect("file exists").len()))
+    };
+
+    // 7z archives are written with seeking, which is only possible when writing straight into the output file
+    let output_file = match output_file {
+        Some(output_file) if formats.len() == 1 && *formats[0].compression_formats == [SevenZip] => {
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
+
+            let writer = archive::sevenz::build_archive_from_paths(
+                &files,
+                BufWriter::with_capacity(BUFFER_CAPACITY, output_file),
+                options.levels.get(SevenZip).map(|level| level.value(SevenZip) as u32),
+                file_visibility_policy,
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+            )?;
+            writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+            return Ok(());
+        }
+        output_file => output_file,
+    };
+
+    let mut writer: Box<dyn Write> = match output_file {
+        Some(output_file) => Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file)),
+        None => Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, io::stdout())),
     };
 
     for format in formats.iter().flat_map(Extension::iter).skip(1).collect::<Vec<_>>().iter().rev() {
-        writer = chain_writer_encoder(format, writer)?;
+        writer = chain_writer_encoder(format, writer, options)?;
     }
 
     match formats[0].compression_formats[0] {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            let _progress = Progress::new_accessible_aware(
-                total_input_size,
-                precise,
-                Some(Box::new(move || output_file_path.metadata().expect("file exists").len())),
-            );
+        Gzip | Bzip | Lz4 | Xz | LzmaAlone | Lzip | Snappy | Brotli | Lzw | Zstd => {
+            let _progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
 
-            writer = chain_writer_encoder(&formats[0].compression_formats[0], writer)?;
-            let mut reader = fs::File::open(&files[0]).unwrap();
+            writer = chain_writer_encoder(&formats[0].compression_formats[0], writer, options)?;
+            let mut reader = utils::open_input(&files[0])?;
             io::copy(&mut reader, &mut writer)?;
         }
         Tar => {
-            let mut progress = Progress::new_accessible_aware(
-                total_input_size,
-                precise,
-                Some(Box::new(move || output_file_path.metadata().expect("file exists").len())),
-            );
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
 
             archive::tar::build_archive_from_paths(
                 &files,
                 &mut writer,
                 file_visibility_policy,
-                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut io::stdout()),
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
             )?;
             writer.flush()?;
         }
-        Zip => {
-            if formats.len() > 1 {
-                eprintln!("{orange}[WARNING]{reset}", orange = *colors::ORANGE, reset = *colors::RESET);
-                eprintln!("{}", ZIP_IN_MEMORY_LIMITATION_WARNING);
-
-                // give user the option to continue compressing after warning is shown
-                if !user_wants_to_continue(output_dir, question_policy, QuestionAction::Compression)? {
-                    return Ok(false);
-                }
-            }
+        Cpio => {
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
 
-            let mut vec_buffer = io::Cursor::new(vec![]);
-
-            let current_position_fn = {
-                let vec_buffer_ptr = {
-                    struct FlyPtr(*const io::Cursor<Vec<u8>>);
-                    unsafe impl Send for FlyPtr {}
-                    FlyPtr(&vec_buffer as *const _)
-                };
-                Box::new(move || {
-                    let vec_buffer_ptr = &vec_buffer_ptr;
-                    // Safety: ptr is valid and vec_buffer is still alive
-                    unsafe { &*vec_buffer_ptr.0 }.position()
-                })
-            };
+            archive::cpio::build_archive_from_paths(
+                &files,
+                &mut writer,
+                file_visibility_policy,
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+            )?;
+            writer.flush()?;
+        }
+        Ar => {
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
 
-            let mut progress = Progress::new_accessible_aware(total_input_size, precise, Some(current_position_fn));
+            archive::ar::build_archive_from_paths(
+                &files,
+                &mut writer,
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+            )?;
+            writer.flush()?;
+        }
+        Zip => {
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
 
             archive::zip::build_archive_from_paths(
                 &files,
-                &mut vec_buffer,
+                &mut writer,
+                options.levels.get(Zip).map(|level| level.value(Zip)),
+                options.zip_method,
+                options.password.as_deref(),
+                file_visibility_policy,
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+            )?;
+            writer.flush()?;
+        }
+        SevenZip => {
+            let mut progress = Progress::new_accessible_aware(total_input_size, precise, current_position_fn());
+
+            // The archive is built in a temporary file, as it can't be written through the other formats
+            // Placed next to the output, the system's temporary directory might be in memory
+            let temp_file = match output_file_path.as_deref().and_then(Path::parent) {
+                Some(dir) => tempfile::tempfile_in(dir)?,
+                None => tempfile::tempfile()?,
+            };
+            let temp_file = archive::sevenz::build_archive_from_paths(
+                &files,
+                BufWriter::with_capacity(BUFFER_CAPACITY, temp_file),
+                options.levels.get(SevenZip).map(|level| level.value(SevenZip) as u32),
                 file_visibility_policy,
-                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut io::stdout()),
+                progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
             )?;
-            let vec_buffer = vec_buffer.into_inner();
-            io::copy(&mut vec_buffer.as_slice(), &mut writer)?;
+            let mut temp_file = temp_file.into_inner().map_err(io::IntoInnerError::into_error)?;
+            temp_file.seek(io::SeekFrom::Start(0))?;
+
+            io::copy(&mut temp_file, &mut writer)?;
+            writer.flush()?;
         }
+        Rar => unreachable!("RAR archives can't be created"),
     }
 
-    Ok(true)
+    Ok(())
 }
 
 // Decompress a file
 //
-// File at input_file_path is opened for reading, example: "archive.tar.gz"
+// File at input_file_path is opened for reading, example: "archive.tar.gz", or "-" for stdin
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+//   it can only be empty for stdin, in which case the formats are detected from its content
 // output_dir it's where the file will be decompressed to, this function assumes that the directory exists
 // output_file_path is only used when extracting single file formats, not archive formats like .tar or .zip
+//   single file formats read from stdin are written to stdout instead
+#[allow(clippy::too_many_arguments)]
 fn decompress_file(
     input_file_path: &Path,
-    formats: Vec<Extension>,
+    mut formats: Vec<Extension>,
     output_dir: &Path,
     output_file_path: PathBuf,
+    password: Option<&str>,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
+    options: &DecompressionOptions,
     question_policy: QuestionPolicy,
 ) -> crate::Result<()> {
     assert!(output_dir.exists());
-    let total_input_size = input_file_path.metadata().expect("file exists").len();
-    let reader = fs::File::open(&input_file_path)?;
-    // Zip archives are special, because they require io::Seek, so it requires it's logic separated
-    // from decoder chaining.
+    let input_is_stdin = is_stdio(input_file_path);
+    // The size of stdin is unknown
+    let total_input_size = if input_is_stdin { 0 } else { input_file_path.metadata().expect("file exists").len() };
+    // Zip and 7z archives are special, because they require io::Seek, and RAR archives can only be read
+    // from a path, so it requires it's logic separated from decoder chaining.
     //
     // This is the only case where we can read and unpack it directly, without having to do
-    // in-memory decompression/copying first.
+    // decompression/copying first.
     //
-    // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if formats.len() == 1 && *formats[0].compression_formats == [Zip] {
-        let zip_archive = zip::ZipArchive::new(reader)?;
-        let files = if let ControlFlow::Continue(files) = smart_unpack(
-            Box::new(move |output_dir| {
-                let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
-                crate::archive::zip::unpack_archive(
-                    zip_archive,
-                    output_dir,
-                    progress.as_mut().map(Progress::display_handle).unwrap_or(&mut io::stdout()),
-                )
-            }),
-            output_dir,
-            &output_file_path,
-            question_policy,
-        )? {
+    // Any other Zip, 7z or RAR decompression is first decoded into a temporary file, see `decode_into_temp_file`
+    // and `decode_into_named_temp_file`.
+    if !input_is_stdin && formats.len() == 1 && matches!(formats[0].compression_formats, [Zip] | [SevenZip] | [Rar]) {
+        let unpack_fn: UnpackFn<'_> = match formats[0].compression_formats[0] {
+            Zip => {
+                let reader = fs::File::open(input_file_path)?;
+                let mut zip_archive = zip::ZipArchive::new(reader)?;
+                let password = zip_password(&mut zip_archive, input_file_path, password)?;
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    crate::archive::zip::unpack_archive(
+                        zip_archive,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        password.as_deref().map(str::as_bytes),
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                })
+            }
+            SevenZip => {
+                let reader = fs::File::open(input_file_path)?;
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    crate::archive::sevenz::unpack_archive(
+                        BufReader::with_capacity(BUFFER_CAPACITY, reader),
+                        output_dir,
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                })
+            }
+            Rar => {
+                let password = rar_password(input_file_path, input_file_path, password)?;
+                let archive_path = input_file_path.to_owned();
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    crate::archive::rar::unpack_archive(
+                        &archive_path,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        password.as_deref().map(str::as_bytes),
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                })
+            }
+            _ => unreachable!("checked above"),
+        };
+        let files = if let ControlFlow::Continue(files) =
+            smart_unpack(unpack_fn, output_dir, &output_file_path, filter, rewrite, question_policy)?
+        {
             files
         } else {
             return Ok(());
@@ -468,32 +940,76 @@ fn decompress_file(
         return Ok(());
     }
 
+    // Tarballs in the seekable format of zstd are read with random access, the frames of the files that aren't
+    // unpacked are skipped
+    let is_tar_zst = matches!(formats.iter().flat_map(Extension::iter).collect::<Vec<_>>()[..], [Tar, Zstd]);
+    if !input_is_stdin && is_tar_zst {
+        let reader = fs::File::open(input_file_path)?;
+        if let Some(decoder) = SeekableZstdDecoder::new(reader, options.zstd_dict.as_deref())? {
+            let files = if let ControlFlow::Continue(files) = smart_unpack(
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    crate::archive::tar::unpack_seekable_archive(
+                        decoder,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                }),
+                output_dir,
+                &output_file_path,
+                filter,
+                rewrite,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            };
+
+            info!(accessible, "Successfully decompressed archive in {}.", nice_directory_display(output_dir));
+            info!(accessible, "Files unpacked: {}", files.len());
+
+            return Ok(());
+        }
+    }
+
+    let mut reader = utils::open_input(input_file_path)?;
+    if formats.is_empty() {
+        (formats, reader) = detect_stdin_formats(reader, options)?;
+    }
+
     // Will be used in decoder chaining
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
-    let mut reader: Box<dyn Read> = Box::new(reader);
-
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            Tar | Zip => unreachable!(),
-        };
-        Ok(decoder)
-    };
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
 
     for format in formats.iter().flat_map(Extension::iter).skip(1).collect::<Vec<_>>().iter().rev() {
-        reader = chain_reader_decoder(format, reader)?;
+        reader = chain_reader_decoder(format, reader, options)?;
     }
 
     let files_unpacked;
     match formats[0].compression_formats[0] {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            reader = chain_reader_decoder(&formats[0].compression_formats[0], reader)?;
+        Gzip | Bzip | Lz4 | Xz | LzmaAlone | Lzip | Snappy | Brotli | Lzw | Zstd => {
+            if filter.is_selective() || rewrite.is_active() {
+                let error = FinalError::with_title(format!("Cannot decompress '{}'.", to_utf(input_file_path)))
+                    .detail("Only the members of archives can be selected and have their paths rewritten")
+                    .detail(format!("'{}' is not an archive", formats[0]));
+
+                return Err(error.into());
+            }
+            reader = chain_reader_decoder(&formats[0].compression_formats[0], reader, options)?;
+
+            if input_is_stdin {
+                let _progress = Progress::new_accessible_aware(0, false, None);
+
+                let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, io::stdout());
+                io::copy(&mut reader, &mut writer)?;
+                writer.flush()?;
+
+                info!(accessible, "Successfully decompressed stdin into stdout.");
+                return Ok(());
+            }
 
             let writer = utils::create_or_ask_overwrite(&output_file_path, question_policy)?;
             if writer.is_none() {
@@ -514,15 +1030,19 @@ fn decompress_file(
         Tar => {
             files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
                 Box::new(move |output_dir| {
-                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
                     crate::archive::tar::unpack_archive(
                         reader,
                         output_dir,
-                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut io::stdout()),
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
                     )
                 }),
                 output_dir,
                 &output_file_path,
+                filter,
+                rewrite,
                 question_policy,
             )? {
                 files
@@ -530,32 +1050,128 @@ fn decompress_file(
                 return Ok(());
             };
         }
-        Zip => {
-            if formats.len() > 1 {
-                eprintln!("{orange}[WARNING]{reset}", orange = *colors::ORANGE, reset = *colors::RESET);
-                eprintln!("{}", ZIP_IN_MEMORY_LIMITATION_WARNING);
-
-                // give user the option to continue decompressing after warning is shown
-                if !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
-                }
-            }
-
-            let mut vec = vec![];
-            io::copy(&mut reader, &mut vec)?;
-            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+        Cpio => {
+            files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
+                    crate::archive::cpio::unpack_archive(
+                        reader,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                }),
+                output_dir,
+                &output_file_path,
+                filter,
+                rewrite,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            };
+        }
+        Ar => {
+            files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
+                    crate::archive::ar::unpack_archive(
+                        reader,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                }),
+                output_dir,
+                &output_file_path,
+                filter,
+                rewrite,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            };
+        }
+        Zip => {
+            // Placed next to the output, the system's temporary directory might be in memory
+            let temp_file = decode_into_temp_file(&mut reader, Some(output_dir))?;
+            let mut zip_archive = zip::ZipArchive::new(temp_file)?;
+            let password = zip_password(&mut zip_archive, input_file_path, password)?;
 
             files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
                 Box::new(move |output_dir| {
-                    let mut progress = Progress::new_accessible_aware(total_input_size, true, None);
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
                     crate::archive::zip::unpack_archive(
                         zip_archive,
                         output_dir,
-                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut io::stdout()),
+                        filter,
+                        rewrite,
+                        password.as_deref().map(str::as_bytes),
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                }),
+                output_dir,
+                &output_file_path,
+                filter,
+                rewrite,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            };
+        }
+        SevenZip => {
+            // Placed next to the output, the system's temporary directory might be in memory
+            let temp_file = decode_into_temp_file(&mut reader, Some(output_dir))?;
+
+            files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
+                    crate::archive::sevenz::unpack_archive(
+                        temp_file,
+                        output_dir,
+                        filter,
+                        rewrite,
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
+                    )
+                }),
+                output_dir,
+                &output_file_path,
+                filter,
+                rewrite,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            };
+        }
+        Rar => {
+            // Placed next to the output, the system's temporary directory might be in memory
+            let temp_file = decode_into_named_temp_file(&mut reader, Some(output_dir))?;
+            let password = rar_password(temp_file.path(), input_file_path, password)?;
+
+            files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+                Box::new(move |output_dir| {
+                    let mut progress = Progress::new_accessible_aware(total_input_size, !input_is_stdin, None);
+                    crate::archive::rar::unpack_archive(
+                        temp_file.path(),
+                        output_dir,
+                        filter,
+                        rewrite,
+                        password.as_deref().map(str::as_bytes),
+                        progress.as_mut().map(Progress::display_handle).unwrap_or(&mut *message_output()),
                     )
                 }),
                 output_dir,
                 &output_file_path,
+                filter,
+                rewrite,
                 question_policy,
             )? {
                 files
@@ -575,74 +1191,103 @@ fn decompress_file(
     Ok(())
 }
 
-// File at input_file_path is opened for reading, example: "archive.tar.gz"
+// File at input_file_path is opened for reading, example: "archive.tar.gz", or "-" for stdin
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+//   it can only be empty for stdin, in which case the formats are detected from its content
 fn list_archive_contents(
     archive_path: &Path,
-    formats: Vec<CompressionFormat>,
+    mut formats: Vec<CompressionFormat>,
     list_options: ListOptions,
-    question_policy: QuestionPolicy,
+    password: Option<&str>,
+    options: &DecompressionOptions,
 ) -> crate::Result<()> {
-    let reader = fs::File::open(&archive_path)?;
-
-    // Zip archives are special, because they require io::Seek, so it requires it's logic separated
+    // Zip and 7z archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
     // This is the only case where we can read and unpack it directly, without having to do
-    // in-memory decompression/copying first.
+    // decompression/copying first.
     //
-    // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if let [Zip] = *formats.as_slice() {
-        let zip_archive = zip::ZipArchive::new(reader)?;
-        let files = crate::archive::zip::list_archive(zip_archive);
+    // Any other Zip or 7z decompression is first decoded into a temporary file, see `decode_into_temp_file`.
+    if let ([Zip], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let reader = fs::File::open(archive_path)?;
+        let mut zip_archive = zip::ZipArchive::new(reader)?;
+        let password = zip_password(&mut zip_archive, archive_path, password)?;
+        let files = crate::archive::zip::list_archive(zip_archive, password.map(String::into_bytes));
+        list::list_files(archive_path, files, list_options)?;
+
+        return Ok(());
+    }
+    if let ([SevenZip], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let reader = BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(archive_path)?);
+        let files = crate::archive::sevenz::list_archive(reader)?;
         list::list_files(archive_path, files, list_options)?;
 
         return Ok(());
     }
+    if let ([Rar], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let password = rar_password(archive_path, archive_path, password)?;
+        let files = crate::archive::rar::list_archive(archive_path, password.as_deref().map(str::as_bytes))?;
+        list::list_files(archive_path, files, list_options)?;
+
+        return Ok(());
+    }
+    // Only the frames with the headers of the files are decompressed from tarballs in the seekable format of zstd
+    if let ([Tar, Zstd], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let reader = fs::File::open(archive_path)?;
+        if let Some(decoder) = SeekableZstdDecoder::new(reader, options.zstd_dict.as_deref())? {
+            let files = crate::archive::tar::list_seekable_archive(tar::Archive::new(decoder));
+            list::list_files(archive_path, files, list_options)?;
+
+            return Ok(());
+        }
+    }
+
+    let mut reader = utils::open_input(archive_path)?;
+    if formats.is_empty() {
+        let extensions;
+        (extensions, reader) = detect_stdin_formats(reader, options)?;
+
+        if !extensions[0].is_archive() {
+            let error = FinalError::with_title("Cannot list archive contents")
+                .detail("Only archives can have their contents listed")
+                .detail(format!("stdin was detected as '{}', which is not an archive", extensions[0]));
+
+            return Err(error.into());
+        }
+        formats = extensions.iter().flat_map(Extension::iter).copied().collect();
+    }
 
     // Will be used in decoder chaining
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read + Send> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder =
-        |format: &CompressionFormat, decoder: Box<dyn Read + Send>| -> crate::Result<Box<dyn Read + Send>> {
-            let decoder: Box<dyn Read + Send> = match format {
-                Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-                Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-                Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
-                Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-                Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-                Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-                Tar | Zip => unreachable!(),
-            };
-            Ok(decoder)
-        };
-
     for format in formats.iter().skip(1).rev() {
-        reader = chain_reader_decoder(format, reader)?;
+        reader = chain_reader_decoder(format, reader, options)?;
     }
 
     let files: Box<dyn Iterator<Item = crate::Result<FileInArchive>>> = match formats[0] {
         Tar => Box::new(crate::archive::tar::list_archive(tar::Archive::new(reader))),
+        Cpio => Box::new(crate::archive::cpio::list_archive(reader)),
+        Ar => Box::new(crate::archive::ar::list_archive(reader)),
         Zip => {
-            if formats.len() > 1 {
-                eprintln!("{orange}[WARNING]{reset}", orange = *colors::ORANGE, reset = *colors::RESET);
-                eprintln!("{}", ZIP_IN_MEMORY_LIMITATION_WARNING);
+            let temp_file = decode_into_temp_file(&mut reader, None)?;
+            let mut zip_archive = zip::ZipArchive::new(temp_file)?;
+            let password = zip_password(&mut zip_archive, archive_path, password)?;
 
-                // give user the option to continue decompressing after warning is shown
-                if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
-                }
-            }
+            Box::new(crate::archive::zip::list_archive(zip_archive, password.map(String::into_bytes)))
+        }
+        SevenZip => {
+            let temp_file = decode_into_temp_file(&mut reader, None)?;
 
-            let mut vec = vec![];
-            io::copy(&mut reader, &mut vec)?;
-            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+            Box::new(crate::archive::sevenz::list_archive(temp_file)?)
+        }
+        Rar => {
+            let temp_file = decode_into_named_temp_file(&mut reader, None)?;
+            let password = rar_password(temp_file.path(), archive_path, password)?;
 
-            Box::new(crate::archive::zip::list_archive(zip_archive))
+            Box::new(crate::archive::rar::list_archive(temp_file.path(), password.as_deref().map(str::as_bytes))?)
         }
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
+        Gzip | Bzip | Lz4 | Xz | LzmaAlone | Lzip | Snappy | Brotli | Lzw | Zstd => {
             panic!("Not an archive! This should never happen, if it does, something is wrong with `CompressionFormat::is_archive()`. Please report this error!");
         }
     };
@@ -650,14 +1295,618 @@ fn list_archive_contents(
     Ok(())
 }
 
+// Writes `member` of the archive to stdout, or the whole decoded file for single file formats
+//
+// archive_path is the archive or compressed file, or "-" for stdin
+// formats are the formats of the archive, detected from stdin if empty, example: [Tar, Gz] (in compression order)
+// member is the path of the file inside of the archive, and None for single file formats
+fn cat(
+    archive_path: &Path,
+    mut formats: Vec<CompressionFormat>,
+    member: Option<&Path>,
+    password: Option<&str>,
+    options: &DecompressionOptions,
+) -> crate::Result<()> {
+    let stdout = io::stdout();
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, stdout.lock());
+
+    let mut reader = utils::open_input(archive_path)?;
+    if formats.is_empty() {
+        let extensions;
+        (extensions, reader) = detect_stdin_formats(reader, options)?;
+        formats = extensions.iter().flat_map(Extension::iter).copied().collect();
+    }
+
+    let member = match (formats[0].is_archive_format(), member) {
+        (true, Some(member)) => member,
+        (true, None) => {
+            let error = FinalError::with_title(format!("Cannot write '{}' to stdout", to_utf(archive_path)))
+                .detail("It's an archive, so the file to write must be chosen")
+                .hint("Give the path of the file inside of the archive, as shown by 'ouch list':")
+                .hint(format!("  ouch cat {} path/to/file", to_utf(archive_path)));
+
+            return Err(error.into());
+        }
+        (false, Some(member)) => {
+            let mut error = FinalError::with_title(format!("Cannot write '{}' to stdout", member.display()))
+                .detail(format!("'{}' is not an archive, it only holds a single file", to_utf(archive_path)))
+                .hint(format!("Use 'ouch cat {}' to write its decompressed contents", to_utf(archive_path)));
+            // Only the outer format of stdin is detected, like the gz of a tar.gz
+            if is_stdio(archive_path) {
+                error = error.hint("Or give the formats of the archive with '--format', e.g. '--format tar.gz'");
+            }
+
+            return Err(error.into());
+        }
+        // Single file formats are simply decoded
+        (false, None) => {
+            let mut reader: Box<dyn Read + Send> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, reader));
+            for format in formats.iter().rev() {
+                reader = chain_reader_decoder(format, reader, options)?;
+            }
+            io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+
+            return Ok(());
+        }
+    };
+
+    if !cat_member(archive_path, reader, &formats, member, password, options, &mut writer)? {
+        let error = FinalError::with_title(format!("Could not find '{}'", member.display()))
+            .detail(format!("'{}' is not in the archive '{}'", member.display(), to_utf(archive_path)))
+            .hint("Use 'ouch list' to see the paths of the members");
+
+        return Err(error.into());
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes the contents of `member` to `writer`, returns false if the archive doesn't have it
+//
+// reader reads the archive at archive_path from its start, it isn't used by the archives read straight from the file
+fn cat_member(
+    archive_path: &Path,
+    reader: Box<dyn Read + Send>,
+    formats: &[CompressionFormat],
+    member: &Path,
+    password: Option<&str>,
+    options: &DecompressionOptions,
+    writer: &mut impl Write,
+) -> crate::Result<bool> {
+    // Zip, 7z and RAR archives are read straight from the file when they aren't chained, like in `decompress_file`
+    if let ([Zip], false) = (formats, is_stdio(archive_path)) {
+        let mut zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+        let password = zip_password(&mut zip_archive, archive_path, password)?;
+        return archive::zip::cat_member(zip_archive, member, password.as_deref().map(str::as_bytes), writer);
+    }
+    if let ([SevenZip], false) = (formats, is_stdio(archive_path)) {
+        let reader = BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(archive_path)?);
+        return archive::sevenz::cat_member(reader, member, writer);
+    }
+    if let ([Rar], false) = (formats, is_stdio(archive_path)) {
+        let password = rar_password(archive_path, archive_path, password)?;
+        return archive::rar::cat_member(archive_path, member, password.as_deref().map(str::as_bytes), writer);
+    }
+    // Only the frames from the member's header onwards are decompressed from tarballs in the seekable format of zstd
+    if let ([Tar, Zstd], false) = (formats, is_stdio(archive_path)) {
+        let reader = fs::File::open(archive_path)?;
+        if let Some(decoder) = SeekableZstdDecoder::new(reader, options.zstd_dict.as_deref())? {
+            return archive::tar::cat_seekable_member(decoder, member, writer);
+        }
+    }
+
+    let mut reader: Box<dyn Read + Send> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, reader));
+    for format in formats.iter().skip(1).rev() {
+        reader = chain_reader_decoder(format, reader, options)?;
+    }
+
+    match formats[0] {
+        Tar => archive::tar::cat_member(reader, member, writer),
+        Cpio => archive::cpio::cat_member(reader, member, writer),
+        Ar => archive::ar::cat_member(reader, member, writer),
+        Zip => {
+            let mut zip_archive = zip::ZipArchive::new(decode_into_temp_file(&mut reader, None)?)?;
+            let password = zip_password(&mut zip_archive, archive_path, password)?;
+            archive::zip::cat_member(zip_archive, member, password.as_deref().map(str::as_bytes), writer)
+        }
+        SevenZip => archive::sevenz::cat_member(decode_into_temp_file(&mut reader, None)?, member, writer),
+        Rar => {
+            let temp_file = decode_into_named_temp_file(&mut reader, None)?;
+            let password = rar_password(temp_file.path(), archive_path, password)?;
+            archive::rar::cat_member(temp_file.path(), member, password.as_deref().map(str::as_bytes), writer)
+        }
+        Gzip | Bzip | Lz4 | Xz | LzmaAlone | Lzip | Snappy | Brotli | Lzw | Zstd => {
+            unreachable!("single file formats are decoded by `cat`")
+        }
+    }
+}
+
+// Decodes the whole file at archive_path, reading every member of archives, which verifies the checksums of the formats
+//
+// formats are the formats of the file, detected from stdin if empty, example: [Tar, Gz] (in compression order)
+// position is where the test is, which tells where the file is corrupt when it fails
+fn test_archive(
+    archive_path: &Path,
+    mut formats: Vec<CompressionFormat>,
+    password: Option<&str>,
+    options: &DecompressionOptions,
+    position: &mut TestPosition,
+) -> crate::Result<()> {
+    // Zip, 7z and RAR archives are read straight from the file when they aren't chained, like in `decompress_file`
+    if let ([Zip], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let mut zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+        let password = zip_password(&mut zip_archive, archive_path, password)?;
+        return archive::zip::test_archive(zip_archive, password.as_deref().map(str::as_bytes), position);
+    }
+    if let ([SevenZip], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let reader = BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(archive_path)?);
+        return archive::sevenz::test_archive(reader, position);
+    }
+    if let ([Rar], false) = (formats.as_slice(), is_stdio(archive_path)) {
+        let password = rar_password(archive_path, archive_path, password)?;
+        return archive::rar::test_archive(archive_path, password.as_deref().map(str::as_bytes), position);
+    }
+
+    let mut reader = utils::open_input(archive_path)?;
+    if formats.is_empty() {
+        if !is_stdio(archive_path) {
+            let error = FinalError::with_title(format!("Cannot test '{}'", to_utf(archive_path)))
+                .detail("Its format can't be detected from its extension")
+                .hint("Choose the format with the '--format' flag:")
+                .hint(format!("  ouch test {} --format tar.gz", to_utf(archive_path)));
+
+            return Err(error.into());
+        }
+        let extensions;
+        (extensions, reader) = detect_stdin_formats(reader, options)?;
+        formats = extensions.iter().flat_map(Extension::iter).copied().collect();
+    }
+
+    let mut reader: Box<dyn Read + Send> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, reader));
+    // The decoders of single file formats are chained too, their contents are read as a whole
+    let archive_format = formats.first().filter(|format| format.is_archive_format()).copied();
+    let skip = usize::from(archive_format.is_some());
+    for format in formats.iter().skip(skip).rev() {
+        reader = chain_reader_decoder(format, reader, options)?;
+    }
+
+    match archive_format {
+        Some(Tar) => archive::tar::test_archive(reader, position),
+        Some(Cpio) => archive::cpio::test_archive(reader, position),
+        Some(Ar) => archive::ar::test_archive(reader, position),
+        Some(Zip) => {
+            let mut zip_archive = zip::ZipArchive::new(decode_into_temp_file(&mut reader, None)?)?;
+            let password = zip_password(&mut zip_archive, archive_path, password)?;
+            archive::zip::test_archive(zip_archive, password.as_deref().map(str::as_bytes), position)
+        }
+        Some(SevenZip) => archive::sevenz::test_archive(decode_into_temp_file(&mut reader, None)?, position),
+        Some(Rar) => {
+            let temp_file = decode_into_named_temp_file(&mut reader, None)?;
+            let password = rar_password(temp_file.path(), archive_path, password)?;
+            archive::rar::test_archive(temp_file.path(), password.as_deref().map(str::as_bytes), position)
+        }
+        Some(Gzip | Bzip | Lz4 | Xz | LzmaAlone | Lzip | Snappy | Brotli | Lzw | Zstd) => {
+            unreachable!("only archive formats are kept")
+        }
+        None => Ok(position.read_to_end(reader)?),
+    }
+}
+
+// Converts the file at input_path into other formats, streaming its decoded data into the encoders of the output
+//
+// input_formats are the formats of the input, detected from stdin if empty, example: [Tar, Gz] (in compression order)
+// output_file is the file the output is written to, or None for stdout
+// output_formats are the formats of the output, example: [Tar, Zstd] (in compression order)
+//
+// The data of archives is copied as it is when they're converted into the same archive format, tar archives are
+// converted into zip archives and the other way around member by member
+fn convert_file(
+    input_path: &Path,
+    mut input_formats: Vec<CompressionFormat>,
+    output_file: Option<fs::File>,
+    output_formats: Vec<CompressionFormat>,
+    password: Option<&str>,
+    options: &CompressionOptions,
+) -> crate::Result<()> {
+    let mut reader = utils::open_input(input_path)?;
+    if input_formats.is_empty() {
+        let extensions;
+        (extensions, reader) = detect_stdin_formats(reader, &DecompressionOptions::default())?;
+        input_formats = extensions.iter().flat_map(Extension::iter).copied().collect();
+    }
+
+    let input_archive = input_formats.first().copied().filter(CompressionFormat::is_archive_format);
+    let output_archive = output_formats.first().copied().filter(CompressionFormat::is_archive_format);
+    let formats_text = |formats: &[CompressionFormat]| formats.iter().map(ToString::to_string).collect::<String>();
+    match (input_archive, output_archive) {
+        (None, None) | (Some(Tar), Some(Zip)) | (Some(Zip), Some(Tar)) => {}
+        (Some(input), Some(output)) if input == output => {}
+        (Some(input), Some(output)) => {
+            let error =
+                FinalError::with_title(format!("Cannot convert '{}' archives into '{}' archives", input, output))
+                    .detail("Only tar and zip archives can be converted into each other")
+                    .hint("Decompress the archive and compress its files instead");
+
+            return Err(error.into());
+        }
+        (Some(input), None) => {
+            let error = FinalError::with_title(format!("Cannot convert '{}'", to_utf(input_path)))
+                .detail(format!("It's a '{}' archive, which can only be converted into another archive", input))
+                .hint(format!("Try converting into '{}{}' instead", input, formats_text(&output_formats)));
+
+            return Err(error.into());
+        }
+        (None, Some(output)) => {
+            let mut error = FinalError::with_title(format!("Cannot convert '{}'", to_utf(input_path)))
+                .detail(format!("It holds a single file, which can't be converted into a '{}' archive", output))
+                .hint("Use 'ouch compress' to put it in an archive");
+            if output_formats.len() > 1 {
+                error = error.hint(format!("Or convert it into '{}' only", formats_text(&output_formats[1..])));
+            }
+            if is_stdio(input_path) {
+                error = error.hint("If stdin holds an archive, choose its format with the '--input-format' flag");
+            }
+
+            return Err(error.into());
+        }
+    }
+
+    let mut writer: Box<dyn Write> = match output_file {
+        Some(output_file) => Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file)),
+        None => Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, io::stdout())),
+    };
+    for format in output_formats.iter().skip(usize::from(output_archive.is_some())).rev() {
+        writer = chain_writer_encoder(format, writer, options)?;
+    }
+
+    // Zip archives need to be seekable to be read, the chained ones are decoded into a temporary file first
+    if let (Some(Zip), Some(Tar)) = (input_archive, output_archive) {
+        let mut zip_archive = if let ([Zip], false) = (input_formats.as_slice(), is_stdio(input_path)) {
+            let (file, _) = fs::File::open(input_path)?.into_parts();
+            zip::ZipArchive::new(BufReader::with_capacity(BUFFER_CAPACITY, file))?
+        } else {
+            let mut reader: Box<dyn Read + Send> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, reader));
+            for format in input_formats.iter().skip(1).rev() {
+                reader = chain_reader_decoder(format, reader, &DecompressionOptions::default())?;
+            }
+            zip::ZipArchive::new(decode_into_temp_file(&mut reader, None)?)?
+        };
+        let password = zip_password(&mut zip_archive, input_path, password)?;
+
+        archive::tar::build_archive_from_zip(
+            zip_archive,
+            &mut writer,
+            password.as_deref().map(str::as_bytes),
+            &mut *message_output(),
+        )?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    let mut reader: Box<dyn Read + Send> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, reader));
+    for format in input_formats.iter().skip(usize::from(input_archive.is_some())).rev() {
+        reader = chain_reader_decoder(format, reader, &DecompressionOptions::default())?;
+    }
+
+    if let (Some(Tar), Some(Zip)) = (input_archive, output_archive) {
+        archive::zip::build_archive_from_tar(
+            tar::Archive::new(reader),
+            &mut writer,
+            options.levels.get(Zip).map(|level| level.value(Zip)),
+            options.zip_method,
+            &mut *message_output(),
+        )?;
+    } else {
+        // Archives converted into the same archive format are copied as they are, like the data of single files
+        io::copy(&mut reader, &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Trains a zstd dictionary of at most `max_size` bytes on the files given by `samples`, and on the files inside of
+/// the directories, writing it to `output_path`
+fn train_zstd_dict(
+    samples: &[PathBuf],
+    output_path: &Path,
+    max_size: usize,
+    file_visibility_policy: FileVisibilityPolicy,
+    question_policy: QuestionPolicy,
+) -> crate::Result<()> {
+    let mut sample_files = vec![];
+    for sample in samples {
+        for entry in file_visibility_policy.build_walker(sample) {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+                sample_files.push(entry.into_path());
+            }
+        }
+    }
+
+    if output_path.exists() && !utils::user_wants_to_overwrite(output_path, question_policy)? {
+        // User does not want to overwrite this file, skip and return without any errors
+        return Ok(());
+    }
+
+    let dict = zstd::dict::from_files(&sample_files, max_size).map_err(|err| {
+        FinalError::with_title("Could not train the zstd dictionary")
+            .detail(format!("Error: {}.", err))
+            .detail(format!("Samples: {} files", sample_files.len()))
+            .hint("Training needs lots of small samples, like a few hundred files")
+    })?;
+    fs::write(output_path, dict)?;
+
+    info!(accessible, "Successfully trained '{}' on {} files.", to_utf(output_path), sample_files.len());
+    Ok(())
+}
+
+/// The password used to unpack a zip archive, asked to the user if the archive is encrypted and none was given
+fn zip_password<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    archive_path: &Path,
+    password: Option<&str>,
+) -> crate::Result<Option<String>> {
+    match password {
+        Some(password) => Ok(Some(password.to_owned())),
+        None if archive::zip::has_encrypted_files(archive)? => utils::ask_password(archive_path).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The password used to unpack a RAR archive, asked to the user if the archive is encrypted and none was given
+///
+/// `archive_path` is where the archive is read from, `input_path` is how it's shown to the user.
+fn rar_password(archive_path: &Path, input_path: &Path, password: Option<&str>) -> crate::Result<Option<String>> {
+    match password {
+        Some(password) => Ok(Some(password.to_owned())),
+        None if archive::rar::has_encrypted_files(archive_path)? => utils::ask_password(input_path).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Writes the decoded data of a chained zip or 7z archive (like `.zip.xz`) into a temporary file
+///
+/// Zip and 7z archives need to be seekable to be read, the temporary file keeps the memory usage bounded
+/// regardless of the archive size. It's created in `dir`, or in the system's temporary directory
+/// if `None`, and it's deleted as soon as it's closed.
+fn decode_into_temp_file(reader: &mut dyn Read, dir: Option<&Path>) -> crate::Result<BufReader<std::fs::File>> {
+    let temp_file = match dir {
+        Some(dir) => tempfile::tempfile_in(dir)?,
+        None => tempfile::tempfile()?,
+    };
+
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, temp_file);
+    io::copy(reader, &mut writer)?;
+    let mut temp_file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    temp_file.seek(io::SeekFrom::Start(0))?;
+
+    Ok(BufReader::with_capacity(BUFFER_CAPACITY, temp_file))
+}
+
+/// Writes the decoded data of a chained RAR archive (like `.rar.gz`) into a named temporary file
+///
+/// RAR archives can only be read from a path. It's created in `dir`, or in the system's temporary
+/// directory if `None`, and it's deleted when dropped.
+fn decode_into_named_temp_file(reader: &mut dyn Read, dir: Option<&Path>) -> crate::Result<tempfile::NamedTempFile> {
+    let temp_file = match dir {
+        Some(dir) => tempfile::NamedTempFile::new_in(dir)?,
+        None => tempfile::NamedTempFile::new()?,
+    };
+
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, temp_file);
+    io::copy(reader, &mut writer)?;
+    let temp_file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+
+    Ok(temp_file)
+}
+
+// Grab previous encoder and wrap it inside of a new one
+fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Write>,
+    options: &CompressionOptions,
+) -> crate::Result<Box<dyn Write>> {
+    let level = options.levels.get(*format);
+    let encoder: Box<dyn Write> = match format {
+        Gzip => {
+            let level = level.map_or_else(Default::default, |level| flate2::Compression::new(level.value(Gzip) as u32));
+            if options.threads > 1 {
+                Box::new(ParallelGzEncoder::new(encoder, level, options.threads))
+            } else {
+                Box::new(flate2::write::GzEncoder::new(encoder, level))
+            }
+        }
+        Bzip => {
+            let level = level.map_or_else(Default::default, |level| bzip2::Compression::new(level.value(Bzip) as u32));
+            Box::new(bzip2::write::BzEncoder::new(encoder, level))
+        }
+        Lz4 => {
+            let mut preferences = lzzzz::lz4f::PreferencesBuilder::new();
+            if let Some(level) = level {
+                preferences.compression_level(level.value(Lz4));
+            }
+            // Like the lz4 CLI, so that corrupted files are detected
+            preferences.content_checksum(lzzzz::lz4f::ContentChecksum::Enabled);
+            Box::new(lzzzz::lz4f::WriteCompressor::new(encoder, preferences.build())?)
+        }
+        Xz => {
+            let preset = level.map_or(6, Level::xz_preset);
+            if options.threads > 1 {
+                let stream = xz2::stream::MtStreamBuilder::new()
+                    .threads(options.threads as u32)
+                    .preset(preset)
+                    // Same integrity check as the single threaded encoder
+                    .check(xz2::stream::Check::Crc64)
+                    .encoder()
+                    .map_err(io::Error::from)?;
+                Box::new(xz2::write::XzEncoder::new_stream(encoder, stream))
+            } else {
+                Box::new(xz2::write::XzEncoder::new(encoder, preset))
+            }
+        }
+        LzmaAlone => {
+            // The legacy format has no multithreaded encoder
+            let lzma_options =
+                xz2::stream::LzmaOptions::new_preset(level.map_or(6, Level::xz_preset)).map_err(io::Error::from)?;
+            let stream = xz2::stream::Stream::new_lzma_encoder(&lzma_options).map_err(io::Error::from)?;
+            Box::new(LzmaAloneEncoder(xz2::write::XzEncoder::new_stream(encoder, stream)))
+        }
+        Lzip => {
+            let mut lzip_options =
+                lzma_rust2::LzipOptions::with_preset(level.map_or(6, |level| level.value(Lzip) as u32));
+            if options.threads > 1 {
+                // Each thread compresses its own member, twice the dictionary size like plzip
+                let member_size = 2 * u64::from(lzip_options.lzma_options.dict_size);
+                lzip_options.set_member_size(NonZeroU64::new(member_size));
+                Box::new(lzma_rust2::LzipWriterMt::new(encoder, lzip_options, options.threads as u32)?.auto_finish())
+            } else {
+                Box::new(lzma_rust2::LzipWriter::new(encoder, lzip_options).auto_finish())
+            }
+        }
+        Snappy => Box::new(snap::write::FrameEncoder::new(encoder)),
+        Brotli => {
+            let quality = level.map_or(BROTLI_DEFAULT_QUALITY, |level| level.value(Brotli) as u32);
+            Box::new(brotli::CompressorWriter::new(encoder, BUFFER_CAPACITY, quality, level::brotli_window(quality)))
+        }
+        Lzw => Box::new(LzwEncoder::new(encoder)),
+        Zstd if options.zstd_seekable => {
+            // Level 0 means the zstd default level
+            let level = level.map_or(0, |level| level.value(Zstd));
+            let mut compressor = match &options.zstd_dict {
+                Some(dict) => zstd::bulk::Compressor::with_dictionary(level, dict)?,
+                None => zstd::bulk::Compressor::new(level)?,
+            };
+            // Each frame has a checksum, as the frames are decompressed on their own
+            compressor.set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(true))?;
+            if options.threads > 1 {
+                compressor.set_parameter(zstd::zstd_safe::CParameter::NbWorkers(options.threads as u32))?;
+            }
+            Box::new(SeekableZstdEncoder::new(encoder, compressor))
+        }
+        Zstd => {
+            // Level 0 means the zstd default level
+            let level = level.map_or(0, |level| level.value(Zstd));
+            let mut zstd_encoder = match &options.zstd_dict {
+                // Fails if the dictionary is corrupted
+                Some(dict) => zstd::stream::write::Encoder::with_dictionary(encoder, level, dict)?,
+                // Safety:
+                //     Encoder::new() can only fail if `level` is invalid, but it was validated
+                //     against zstd::compression_level_range() when parsed
+                None => zstd::stream::write::Encoder::new(encoder, level).unwrap(),
+            };
+            // Like the zstd CLI, so that corrupted files are detected
+            zstd_encoder.include_checksum(true)?;
+            if options.threads > 1 {
+                zstd_encoder.multithread(options.threads as u32)?;
+            }
+            if let Some(window_log) = options.zstd_long {
+                zstd_encoder.long_distance_matching(true)?;
+                zstd_encoder.window_log(window_log)?;
+            }
+            Box::new(zstd_encoder.auto_finish())
+        }
+        Tar | Zip | SevenZip | Rar | Cpio | Ar => unreachable!(),
+    };
+    Ok(encoder)
+}
+
+// Grab previous decoder and wrap it inside of a new one
+fn chain_reader_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read + Send>,
+    options: &DecompressionOptions,
+) -> crate::Result<Box<dyn Read + Send>> {
+    let decoder: Box<dyn Read + Send> = match format {
+        Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
+        Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
+        Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
+        Xz => Box::new(xz2::read::XzDecoder::new(decoder)),
+        LzmaAlone => {
+            let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX).map_err(io::Error::from)?;
+            Box::new(xz2::read::XzDecoder::new_stream(decoder, stream))
+        }
+        Lzip => Box::new(lzma_rust2::LzipReader::new(decoder)),
+        Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
+        Brotli => Box::new(brotli::Decompressor::new(decoder, BUFFER_CAPACITY)),
+        Lzw => Box::new(LzwDecoder::new(decoder)?),
+        Zstd => {
+            let mut zstd_decoder = match &options.zstd_dict {
+                Some(dict) => zstd::stream::Decoder::with_dictionary(BufReader::new(decoder), dict)?,
+                None => zstd::stream::Decoder::new(decoder)?,
+            };
+            // Frames compressed with long distance matching, by `--zstd-long` or by the `--long` of the zstd CLI,
+            // have windows larger than the ones zstd accepts by default
+            zstd_decoder.window_log_max(*ZSTD_WINDOW_LOG_RANGE.end())?;
+            Box::new(zstd_decoder)
+        }
+        Tar | Zip | SevenZip | Rar | Cpio | Ar => unreachable!(),
+    };
+    Ok(decoder)
+}
+
+// stdin has no extension, so its format can only be detected by the magic bytes at its start
+//
+// The formats inside of compressed ones are detected too, by decoding the start of stdin, until an archive format is
+// found or nothing more is recognized, so `.tar.gz` is unpacked as an archive instead of being decompressed to a tar.
+//
+// Returns the detected extensions (in compression order) and a reader that still yields the bytes read during detection
+fn detect_stdin_formats(
+    reader: Box<dyn Read + Send>,
+    options: &DecompressionOptions,
+) -> crate::Result<(Vec<Extension>, Box<dyn Read + Send>)> {
+    let (prefix, mut reader) = utils::peek(reader, MAGIC_BYTES_LEN)?;
+
+    let Some(extension) = try_infer_extension_from_bytes(&prefix) else {
+        let error = FinalError::with_title("Cannot detect the format of stdin")
+            .detail("The format of stdin can only be detected by its first bytes, but they weren't recognized")
+            .hint("Use the '--format' flag to choose the format:")
+            .hint("  ouch decompress - --format tar.gz");
+
+        return Err(error.into());
+    };
+
+    // From the outermost format to the innermost one
+    let mut extensions = vec![extension];
+    while !extensions.last().unwrap().is_archive() {
+        let decode = |mut decoder| {
+            for format in extensions.iter().flat_map(|extension| extension.compression_formats.iter().rev()) {
+                decoder = chain_reader_decoder(format, decoder, options)?;
+            }
+            Ok(decoder)
+        };
+        let prefix;
+        (prefix, reader) = utils::peek_decoded(reader, MAGIC_BYTES_LEN, decode);
+
+        // Data that can't be decoded isn't detected further, the error is reported when stdin is decompressed
+        match prefix.ok().as_deref().and_then(try_infer_extension_from_bytes) {
+            Some(extension) => extensions.push(extension),
+            None => break,
+        }
+    }
+    extensions.reverse();
+
+    // Infering the format can have unpredicted consequences, which we should always inform the user about
+    let formats_text = extensions.iter().map(ToString::to_string).collect::<Vec<_>>().join(".");
+    info!(accessible, "Detected the format of stdin as `{}`", formats_text);
+    Ok((extensions, reader))
+}
+
+/// Closure that unpacks an archive into the given directory, returning the unpacked paths
+type UnpackFn<'a> = Box<dyn FnOnce(&Path) -> crate::Result<Vec<PathBuf>> + 'a>;
+
 /// Unpacks an archive with some heuristics
 /// - If the archive contains only one file, it will be extracted to the `output_dir`
 /// - If the archive contains multiple files, it will be extracted to a subdirectory of the output_dir named after the archive (given by `output_file_path`)
+/// - If the paths are rewritten, all the files are extracted to the `output_dir`, as the user chose their paths
+///
 /// Note: This functions assumes that `output_dir` exists
 fn smart_unpack(
-    unpack_fn: Box<dyn FnOnce(&Path) -> crate::Result<Vec<PathBuf>>>,
+    unpack_fn: UnpackFn<'_>,
     output_dir: &Path,
     output_file_path: &Path,
+    filter: &MemberFilter,
+    rewrite: &PathRewrite,
     question_policy: QuestionPolicy,
 ) -> crate::Result<ControlFlow<(), Vec<PathBuf>>> {
     assert!(output_dir.exists());
@@ -671,26 +1920,30 @@ fn smart_unpack(
 
     // unpack the files
     let files = unpack_fn(temp_dir_path)?;
-
-    let root_contains_only_one_element = fs::read_dir(&temp_dir_path)?.count() == 1;
-    if root_contains_only_one_element {
-        // Only one file in the root directory, so we can just move it to the output directory
-        let file = fs::read_dir(&temp_dir_path)?.next().expect("item exists")?;
-        let file_path = file.path();
-        let file_name =
-            file_path.file_name().expect("Should be safe because paths in archives should not end with '..'");
-        let correct_path = output_dir.join(file_name);
-        // One case to handle tough is we need to check if a file with the same name already exists
-        if !utils::clear_path(&correct_path, question_policy)? {
-            return Ok(ControlFlow::Break(()));
+    // Nothing is moved out of the temporary directory if some of the members asked for weren't found
+    filter.check_all_found()?;
+
+    let root_contains_only_one_element = fs::read_dir(temp_dir_path)?.count() == 1;
+    if root_contains_only_one_element || rewrite.is_active() {
+        // Only one file in the root directory, or paths chosen by the user, so we can just move the files to the
+        // output directory
+        for file in fs::read_dir(temp_dir_path)? {
+            let file_path = file?.path();
+            let file_name =
+                file_path.file_name().expect("Should be safe because paths in archives should not end with '..'");
+            let correct_path = output_dir.join(file_name);
+            // One case to handle tough is we need to check if a file with the same name already exists
+            if !utils::clear_path(&correct_path, question_policy)? {
+                return Ok(ControlFlow::Break(()));
+            }
+            fs::rename(&file_path, &correct_path)?;
+            info!(
+                accessible,
+                "Successfully moved {} to {}.",
+                nice_directory_display(&file_path),
+                nice_directory_display(&correct_path)
+            );
         }
-        fs::rename(&file_path, &correct_path)?;
-        info!(
-            accessible,
-            "Successfully moved {} to {}.",
-            nice_directory_display(&file_path),
-            nice_directory_display(&correct_path)
-        );
     } else {
         // Multiple files in the root directory, so:
         // Rename  the temporary directory to the archive name, which is output_file_path
@@ -698,7 +1951,7 @@ fn smart_unpack(
         if !utils::clear_path(output_file_path, question_policy)? {
             return Ok(ControlFlow::Break(()));
         }
-        fs::rename(&temp_dir_path, &output_file_path)?;
+        fs::rename(temp_dir_path, output_file_path)?;
         info!(
             accessible,
             "Successfully moved {} to {}.",
@@ -711,11 +1964,14 @@ fn smart_unpack(
 
 fn check_mime_type(
     files: &[PathBuf],
-    formats: &mut Vec<Vec<Extension>>,
+    formats: &mut [Vec<Extension>],
     question_policy: QuestionPolicy,
 ) -> crate::Result<ControlFlow<()>> {
     for (path, format) in files.iter().zip(formats.iter_mut()) {
-        if format.is_empty() {
+        if is_stdio(path) {
+            // stdin can only be read once, its format is detected later, while decompressing
+            continue;
+        } else if format.is_empty() {
             // File with no extension
             // Try to detect it automatically and prompt the user about it
             if let Some(detected_format) = try_infer_extension(path) {
@@ -732,7 +1988,10 @@ fn check_mime_type(
             // File ending with extension
             // Try to detect the extension and warn the user if it differs from the written one
             let outer_ext = format.iter().next_back().unwrap();
-            if outer_ext != &detected_format {
+            if let Some(fixed_ext) = fix_lzma_container(outer_ext, &detected_format) {
+                info!(accessible, "Detected the `{}` file `{}` as `{}`", outer_ext, path.display(), fixed_ext);
+                *format.last_mut().unwrap() = fixed_ext;
+            } else if outer_ext != &detected_format {
                 warning!(
                     "The file extension: `{}` differ from the detected extension: `{}`",
                     outer_ext,
@@ -751,6 +2010,20 @@ fn check_mime_type(
     Ok(ControlFlow::Continue(()))
 }
 
+/// Older versions wrote xz files with the `.lzma` extension, so xz and LZMA-alone are told apart by their first bytes
+///
+/// Returns the extension with the detected format, or `None` if the extension doesn't need to be fixed
+fn fix_lzma_container(extension: &Extension, detected: &Extension) -> Option<Extension> {
+    let text = match (extension.compression_formats, detected.compression_formats) {
+        ([LzmaAlone], [Xz]) => "xz",
+        ([Tar, LzmaAlone], [Xz]) => "txz",
+        ([Xz], [LzmaAlone]) => "lzma",
+        ([Tar, Xz], [LzmaAlone]) => "tlzma",
+        _ => return None,
+    };
+    extension::to_extension(text)
+}
+
 fn clean_input_files_if_needed(files: &mut Vec<PathBuf>, output_path: &Path) {
     let mut idx = 0;
     while idx < files.len() {
@@ -22,7 +22,7 @@ pub enum Error {
     /// NEEDS MORE CONTEXT
     AlreadyExists { error_title: String },
     /// From zip::result::ZipError::InvalidArchive
-    InvalidZipArchive(&'static str),
+    InvalidZipArchive(CowStr),
     /// Detected from io::Error if .kind() is io::ErrorKind::PermissionDenied
     PermissionDenied { error_title: String },
     /// From zip::result::ZipError::UnsupportedArchive
@@ -127,7 +127,7 @@ impl fmt::Display for Error {
             Error::AlreadyExists { error_title } => {
                 FinalError::with_title(error_title.to_string()).detail("File already exists")
             }
-            Error::InvalidZipArchive(reason) => FinalError::with_title("Invalid zip archive").detail(*reason),
+            Error::InvalidZipArchive(reason) => FinalError::with_title("Invalid zip archive").detail(reason.clone()),
             Error::PermissionDenied { error_title } => {
                 FinalError::with_title(error_title.to_string()).detail("Permission denied")
             }
@@ -168,6 +168,58 @@ impl From<zip::result::ZipError> for Error {
                 }
             }
             ZipError::UnsupportedArchive(filename) => Self::UnsupportedZipArchive(filename),
+            ZipError::InvalidPassword => {
+                Self::Custom {
+                    reason: FinalError::with_title("Invalid password for zip archive")
+                        .detail("The password doesn't match the one used to encrypt the archive"),
+                }
+            }
+            ZipError::CompressionMethodNotSupported(_) => {
+                Self::UnsupportedZipArchive("Compression method not supported")
+            }
+            other => {
+                Self::Custom {
+                    reason: FinalError::with_title("Unexpected error in zip archive").detail(other.to_string()),
+                }
+            }
+        }
+    }
+}
+
+impl From<sevenz_rust2::Error> for Error {
+    fn from(err: sevenz_rust2::Error) -> Self {
+        use sevenz_rust2::Error as SevenZError;
+        match err {
+            SevenZError::Io(io_err, _) | SevenZError::FileOpen(io_err, _) => Self::from(io_err),
+            SevenZError::PasswordRequired | SevenZError::MaybeBadPassword(_) => {
+                Self::Custom {
+                    reason: FinalError::with_title("Cannot unpack encrypted 7z archive")
+                        .detail("Encrypted 7z archives aren't supported"),
+                }
+            }
+            other => Self::Custom { reason: FinalError::with_title("Invalid 7z archive").detail(other.to_string()) },
+        }
+    }
+}
+
+impl From<unrar::error::UnrarError> for Error {
+    fn from(err: unrar::error::UnrarError) -> Self {
+        use unrar::error::Code;
+        match err.code {
+            Code::MissingPassword => {
+                Self::Custom {
+                    reason: FinalError::with_title("Missing password for rar archive")
+                        .detail("The archive is encrypted")
+                        .hint("Use --password to give the password"),
+                }
+            }
+            Code::BadPassword => {
+                Self::Custom {
+                    reason: FinalError::with_title("Invalid password for rar archive")
+                        .detail("The password doesn't match the one used to encrypt the archive"),
+                }
+            }
+            _ => Self::Custom { reason: FinalError::with_title("Invalid rar archive").detail(err.to_string()) },
         }
     }
 }
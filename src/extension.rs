@@ -3,6 +3,7 @@
 use std::{ffi::OsStr, fmt, path::Path};
 
 use self::CompressionFormat::*;
+use crate::error::FinalError;
 
 /// A wrapper around `CompressionFormat` that allows combinations like `tgz`
 #[derive(Debug, Clone, Eq)]
@@ -55,29 +56,49 @@ pub enum CompressionFormat {
     Bzip,
     /// .lz4
     Lz4,
-    /// .xz .lzma
-    Lzma,
+    /// .xz
+    Xz,
+    /// .lzma, the legacy LZMA-alone format replaced by xz
+    LzmaAlone,
+    /// .lz
+    Lzip,
     /// .sz
     Snappy,
-    /// tar, tgz, tbz, tbz2, txz, tlz4, tlzma, tsz, tzst
+    /// .br
+    Brotli,
+    /// .Z, the LZW format of the Unix `compress` command
+    Lzw,
+    /// tar, tgz, tbz, tbz2, txz, tlz, tlz4, tlzma, tsz, tbr, taz, tZ, tzst
     Tar,
     /// .zst
     Zstd,
     /// .zip
     Zip,
+    /// .7z
+    SevenZip,
+    /// .rar, can only be decompressed
+    Rar,
+    /// .cpio
+    Cpio,
+    /// .ar, .a, .deb
+    Ar,
 }
 
 impl CompressionFormat {
-    /// Currently supported archive formats are .tar (and aliases to it) and .zip
+    /// Currently supported archive formats are .tar (and aliases to it), .zip, .7z, .rar, .cpio and .ar
     pub fn is_archive_format(&self) -> bool {
         // Keep this match like that without a wildcard `_` so we don't forget to update it
         match self {
-            Tar | Zip => true,
+            Tar | Zip | SevenZip | Rar | Cpio | Ar => true,
             Gzip => false,
             Bzip => false,
             Lz4 => false,
-            Lzma => false,
+            Xz => false,
+            LzmaAlone => false,
+            Lzip => false,
             Snappy => false,
+            Brotli => false,
+            Lzw => false,
             Zstd => false,
         }
     }
@@ -93,17 +114,57 @@ impl fmt::Display for CompressionFormat {
                 Bzip => ".bz",
                 Zstd => ".zst",
                 Lz4 => ".lz4",
-                Lzma => ".lz",
+                Xz => ".xz",
+                LzmaAlone => ".lzma",
+                Lzip => ".lz",
                 Snappy => ".sz",
+                Brotli => ".br",
+                Lzw => ".Z",
                 Tar => ".tar",
                 Zip => ".zip",
+                SevenZip => ".7z",
+                Rar => ".rar",
+                Cpio => ".cpio",
+                Ar => ".ar",
             }
         )
     }
 }
 
-// use crate::extension::CompressionFormat::*;
-//
+/// Maps a single extension text, like "tgz" or "xz", to its `Extension`
+pub fn to_extension(ext: &str) -> Option<Extension> {
+    let formats: &[CompressionFormat] = match ext {
+        "tar" => &[Tar],
+        "tgz" => &[Tar, Gzip],
+        "tbz" | "tbz2" => &[Tar, Bzip],
+        "tlz" => &[Tar, Lzip],
+        "tlz4" => &[Tar, Lz4],
+        "txz" => &[Tar, Xz],
+        "tlzma" => &[Tar, LzmaAlone],
+        "tsz" => &[Tar, Snappy],
+        "tbr" => &[Tar, Brotli],
+        "taz" | "tZ" => &[Tar, Lzw],
+        "tzst" => &[Tar, Zstd],
+        "zip" => &[Zip],
+        "7z" => &[SevenZip],
+        "rar" => &[Rar],
+        "cpio" => &[Cpio],
+        "ar" | "a" | "deb" => &[Ar],
+        "bz" | "bz2" => &[Bzip],
+        "gz" => &[Gzip],
+        "lz" => &[Lzip],
+        "lz4" => &[Lz4],
+        "xz" => &[Xz],
+        "lzma" => &[LzmaAlone],
+        "sz" => &[Snappy],
+        "br" => &[Brotli],
+        "Z" => &[Lzw],
+        "zst" => &[Zstd],
+        _ => return None,
+    };
+
+    Some(Extension::new(formats, ext))
+}
 
 /// Extracts extensions from a path,
 /// return both the remaining path and the list of extension objects
@@ -119,26 +180,7 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Exten
     let mut extensions = vec![];
 
     // While there is known extensions at the tail, grab them
-    while let Some(extension) = path.extension().and_then(OsStr::to_str) {
-        let formats: &[CompressionFormat] = match extension {
-            "tar" => &[Tar],
-            "tgz" => &[Tar, Gzip],
-            "tbz" | "tbz2" => &[Tar, Bzip],
-            "tlz4" => &[Tar, Lz4],
-            "txz" | "tlzma" => &[Tar, Lzma],
-            "tsz" => &[Tar, Snappy],
-            "tzst" => &[Tar, Zstd],
-            "zip" => &[Zip],
-            "bz" | "bz2" => &[Bzip],
-            "gz" => &[Gzip],
-            "lz4" => &[Lz4],
-            "xz" | "lzma" => &[Lzma],
-            "sz" => &[Snappy],
-            "zst" => &[Zstd],
-            _ => break,
-        };
-
-        let extension = Extension::new(formats, extension);
+    while let Some(extension) = path.extension().and_then(OsStr::to_str).and_then(to_extension) {
         extensions.push(extension);
 
         // Update for the next iteration
@@ -150,6 +192,44 @@ pub fn separate_known_extensions_from_name(mut path: &Path) -> (&Path, Vec<Exten
     (path, extensions)
 }
 
+/// Parses the value given to the `--format` flag, like "tar.gz" or "tgz", into a list of extensions
+///
+/// Fails if the text contains unknown extensions, or if an archive format is found after the first position
+pub fn parse_format(fmt: &OsStr) -> crate::Result<Vec<Extension>> {
+    let fmt = fmt.to_str().ok_or_else(|| {
+        FinalError::with_title(format!("Invalid format '{}'", fmt.to_string_lossy()))
+            .detail("The format must be valid UTF-8")
+    })?;
+
+    // Accept a leading dot, as in `--format .tar.gz`
+    let text = fmt.strip_prefix('.').unwrap_or(fmt);
+
+    let mut extensions = vec![];
+    for ext in text.split('.') {
+        let extension = to_extension(ext).ok_or_else(|| {
+            let error = FinalError::with_title(format!("Invalid format '{}'", fmt));
+            let error = if ext.is_empty() {
+                error.detail("Found an empty extension")
+            } else {
+                error.detail(format!("Unsupported extension '{}'", ext))
+            };
+            error.hint("Formats are extensions separated by dots, like 'tar.gz' or 'tgz' (see --help)")
+        })?;
+        extensions.push(extension);
+    }
+
+    if let Some(extension) = extensions.iter().skip(1).find(|extension| extension.is_archive()) {
+        let error = FinalError::with_title(format!("Invalid format '{}'", fmt))
+            .detail(format!("Found the format '{}' in an incorrect position.", extension))
+            .detail(format!("'{}' can only be used at the start of the format.", extension))
+            .hint(format!("Try moving '{}' to the start of the format.", extension));
+
+        return Err(error.into());
+    }
+
+    Ok(extensions)
+}
+
 /// Extracts extensions from a path, return only the list of extension objects
 pub fn extensions_from_path(path: &Path) -> Vec<Extension> {
     let (_, extensions) = separate_known_extensions_from_name(path);
@@ -170,4 +250,35 @@ mod tests {
 
         assert_eq!(formats, vec![&Tar, &Gzip]);
     }
+
+    #[test]
+    fn test_parse_format() {
+        use CompressionFormat::*;
+        fn formats(text: &str) -> Vec<CompressionFormat> {
+            let extensions = parse_format(OsStr::new(text)).unwrap();
+            extensions.iter().flat_map(Extension::iter).copied().collect()
+        }
+
+        assert_eq!(formats("tar.gz"), vec![Tar, Gzip]);
+        assert_eq!(formats(".tar.gz"), vec![Tar, Gzip]);
+        assert_eq!(formats("tgz"), vec![Tar, Gzip]);
+        assert_eq!(formats("tar.gz.xz"), vec![Tar, Gzip, Xz]);
+        assert_eq!(formats("zst"), vec![Zstd]);
+        assert_eq!(formats("tlz"), vec![Tar, Lzip]);
+        assert_eq!(formats("tar.lz"), vec![Tar, Lzip]);
+        assert_eq!(formats("txz"), vec![Tar, Xz]);
+        assert_eq!(formats("tlzma"), vec![Tar, LzmaAlone]);
+        assert_eq!(formats("tar.lzma"), vec![Tar, LzmaAlone]);
+        assert_eq!(formats("tbr"), vec![Tar, Brotli]);
+        assert_eq!(formats("tar.Z"), vec![Tar, Lzw]);
+        assert_eq!(formats("taz"), vec![Tar, Lzw]);
+        assert_eq!(formats("cpio.gz"), vec![Cpio, Gzip]);
+        assert_eq!(formats("deb"), vec![Ar]);
+
+        assert!(parse_format(OsStr::new("")).is_err());
+        assert!(parse_format(OsStr::new("tar..gz")).is_err());
+        assert!(parse_format(OsStr::new("tar.bin")).is_err());
+        assert!(parse_format(OsStr::new("gz.tar")).is_err());
+        assert!(parse_format(OsStr::new("tar.tgz")).is_err());
+    }
 }
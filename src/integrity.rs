@@ -0,0 +1,96 @@
+//! Integrity tests of archives and compressed files, used by `ouch test`
+
+use std::{
+    fmt,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+/// How far the test of a file went, which tells where it's corrupt when the test fails
+#[derive(Debug, Default)]
+pub struct TestPosition {
+    /// The member of the archive being read, or the last one read to its end
+    member: Option<PathBuf>,
+    /// Bytes of decompressed data read from `member`, or from the whole file for single file formats
+    offset: Option<u64>,
+    /// Whether `member` was read to its end, the corrupt data is then after it
+    member_read: bool,
+}
+
+impl TestPosition {
+    /// Starts testing the next member of an archive
+    pub fn start_member(&mut self, member: impl Into<PathBuf>) {
+        *self = Self { member: Some(member.into()), offset: None, member_read: false };
+    }
+
+    /// Marks the current member as tested, when it's tested by a library that doesn't give its contents
+    pub fn finish_member(&mut self) {
+        self.member_read = true;
+    }
+
+    /// Reads `reader` to its end, the decoders verify the checksums of their formats once they reach it
+    pub fn read_to_end(&mut self, mut reader: impl Read) -> io::Result<()> {
+        let mut buffer = vec![0; 64 * 1024];
+        let offset = self.offset.insert(0);
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => *offset += read as u64,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.member_read = true;
+        Ok(())
+    }
+
+    /// Whether the test read anything, there's no position to tell before that
+    pub fn has_started(&self) -> bool {
+        self.member.is_some() || self.offset.is_some()
+    }
+}
+
+impl fmt::Display for TestPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.member, self.member_read, self.offset) {
+            (Some(member), true, _) => write!(f, "after '{}'", member.display()),
+            (Some(member), false, Some(offset)) => write!(f, "in '{}' at byte {}", member.display(), offset),
+            (Some(member), false, None) => write!(f, "in '{}'", member.display()),
+            (None, _, offset) => write!(f, "at byte {} of the decompressed data", offset.unwrap_or(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let mut position = TestPosition::default();
+        assert!(!position.has_started());
+
+        position.start_member("dir/file");
+        assert_eq!(position.to_string(), "in 'dir/file'");
+
+        let corrupt = io::Cursor::new(vec![0; 100]).chain(FailingReader);
+        assert!(position.read_to_end(corrupt).is_err());
+        assert_eq!(position.to_string(), "in 'dir/file' at byte 100");
+
+        position.start_member("dir/other");
+        position.read_to_end(io::Cursor::new(vec![0; 10])).unwrap();
+        assert_eq!(position.to_string(), "after 'dir/other'");
+
+        let mut position = TestPosition::default();
+        assert!(position.read_to_end(FailingReader).is_err());
+        assert_eq!(position.to_string(), "at byte 0 of the decompressed data");
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt"))
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Compression levels chosen through the `--level`, `--fast` and `--best` flags
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    error::FinalError,
+    extension::{
+        self,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    warning,
+};
+
+/// LZMA_PRESET_EXTREME from liblzma, the flag that turns `9` into `9e`
+const XZ_PRESET_EXTREME: u32 = 1 << 31;
+
+/// Quality used by the brotli CLI when none is given
+pub const BROTLI_DEFAULT_QUALITY: u32 = 11;
+
+/// Largest brotli window, as a power of two, accepted by every decoder (16 MiB)
+const BROTLI_MAX_WINDOW: u32 = 24;
+
+/// A compression level, as passed in the command line
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Level {
+    /// The fastest level of the format (`--fast`)
+    Fastest,
+    /// The level of the format that compresses the most (`--best`)
+    Best,
+    /// An explicit level, like `-l 5` or `-l -3`
+    Exact(i32),
+    /// An explicit xz level with the extreme flag set, like `-l 9e`
+    Extreme(i32),
+}
+
+impl Level {
+    /// Parses a single level, like "5", "-3" or "9e"
+    fn parse(text: &str) -> Option<Self> {
+        match text.strip_suffix('e') {
+            Some(number) => number.parse().ok().map(Level::Extreme),
+            None => text.parse().ok().map(Level::Exact),
+        }
+    }
+
+    /// The numeric value of this level for the given format
+    ///
+    /// The extreme flag isn't part of the value, see [`Level::xz_preset`]
+    pub fn value(self, format: CompressionFormat) -> i32 {
+        match (self, format) {
+            (Level::Exact(level) | Level::Extreme(level), _) => level,
+            (Level::Fastest, Xz | LzmaAlone | Lzip | SevenZip | Brotli) => 0,
+            (Level::Fastest, _) => 1,
+            (Level::Best, Gzip | Bzip | Zip | Xz | LzmaAlone | Lzip | SevenZip) => 9,
+            (Level::Best, Lz4) => lzzzz::lz4f::CLEVEL_MAX,
+            (Level::Best, Brotli) => 11,
+            // Levels above 19 need a lot more memory to decompress, like in the zstd CLI
+            (Level::Best, Zstd) => 19,
+            (Level::Best, Snappy | Lzw | Tar | Rar | Cpio | Ar) => {
+                unreachable!("formats without levels are filtered out")
+            }
+        }
+    }
+
+    /// The xz preset for this level, including the extreme flag, lzma uses the same presets
+    pub fn xz_preset(self) -> u32 {
+        let preset = self.value(Xz) as u32;
+        match self {
+            Level::Extreme(_) => preset | XZ_PRESET_EXTREME,
+            _ => preset,
+        }
+    }
+}
+
+/// The brotli window, as a power of two, used with the given quality
+///
+/// The window grows with the quality, so the fast qualities need less memory to compress and decompress,
+/// from 64 KiB at quality 0 up to 16 MiB from quality 8, which is the brotli CLI's default.
+pub fn brotli_window(quality: u32) -> u32 {
+    (16 + quality).min(BROTLI_MAX_WINDOW)
+}
+
+/// The range of levels accepted by a format, `None` if it has no levels
+fn level_range(format: CompressionFormat) -> Option<RangeInclusive<i32>> {
+    match format {
+        Gzip | Bzip | Zip => Some(1..=9),
+        Lz4 => Some(1..=lzzzz::lz4f::CLEVEL_MAX),
+        Brotli => Some(0..=11),
+        Xz | LzmaAlone | Lzip | SevenZip => Some(0..=9),
+        Zstd => Some(zstd::compression_level_range()),
+        Snappy | Lzw | Tar | Rar | Cpio | Ar => None,
+    }
+}
+
+/// Checks if the level is accepted by the format, and returns a description of the problem otherwise
+fn check_level(level: Level, format: CompressionFormat) -> Result<(), String> {
+    let range = match level_range(format) {
+        Some(range) => range,
+        None => return Err(format!("The format '{}' doesn't support compression levels", format)),
+    };
+
+    match level {
+        Level::Fastest | Level::Best => Ok(()),
+        Level::Extreme(_) if !matches!(format, Xz | LzmaAlone) => {
+            Err(format!("The extreme flag 'e' is only supported by 'xz' and 'lzma', not by '{}'", format))
+        }
+        Level::Exact(value) | Level::Extreme(value) if !range.contains(&value) => {
+            Err(format!(
+                "The level {} is out of the range supported by '{}' ({} to {})",
+                value,
+                format,
+                range.start(),
+                range.end()
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The compression levels for each format of the chain
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CompressionLevels {
+    /// Level applied to every format of the chain, like `-l 5` or `--best`
+    default: Option<Level>,
+    /// Level applied to a single format, like `-l gz=9,xz=3`
+    per_format: Vec<(CompressionFormat, Level)>,
+}
+
+impl CompressionLevels {
+    /// Builds the levels from the `--level`, `--fast` and `--best` flags
+    ///
+    /// The `--level` text is a comma separated list of either a bare level, applied to all formats,
+    /// or `<format>=<level>` pairs, like "9", "-5", "9e" or "gz=9,xz=3".
+    pub fn new(level: Option<&str>, fast: bool, best: bool) -> crate::Result<Self> {
+        let mut levels = Self {
+            default: if fast {
+                Some(Level::Fastest)
+            } else if best {
+                Some(Level::Best)
+            } else {
+                None
+            },
+            per_format: vec![],
+        };
+
+        let text = match level {
+            Some(text) => text,
+            None => return Ok(levels),
+        };
+
+        let invalid_level = |detail: String| {
+            FinalError::with_title(format!("Invalid compression level '{}'", text))
+                .detail(detail)
+                .hint("Examples of valid levels:")
+                .hint("  --level 9")
+                .hint("  --level gz=9,xz=3")
+        };
+
+        for item in text.split(',') {
+            let (format, level_text) = match item.split_once('=') {
+                Some((format, level_text)) => (Some(format), level_text),
+                None => (None, item),
+            };
+            let level = Level::parse(level_text.trim())
+                .ok_or_else(|| invalid_level(format!("'{}' is not a number", level_text)))?;
+
+            match format {
+                None if levels.default.is_some() => {
+                    return Err(invalid_level("Found more than one level for all formats".into()).into());
+                }
+                None => levels.default = Some(level),
+                Some(format_text) => {
+                    let format = parse_single_format(format_text.trim())
+                        .ok_or_else(|| invalid_level(format!("'{}' isn't a single compression format", format_text)))?;
+                    check_level(level, format).map_err(invalid_level)?;
+                    if levels.per_format.iter().any(|(other, _)| *other == format) {
+                        return Err(invalid_level(format!("Found more than one level for '{}'", format)).into());
+                    }
+                    levels.per_format.push((format, level));
+                }
+            }
+        }
+
+        Ok(levels)
+    }
+
+    /// Checks the levels against the formats that are going to be used
+    ///
+    /// A level given to all formats must be valid for each one of them, levels given
+    /// to formats that aren't in the chain are ignored with a warning.
+    pub fn validate(&self, formats: &[Extension]) -> crate::Result<()> {
+        let formats: Vec<CompressionFormat> = formats.iter().flat_map(Extension::iter).copied().collect();
+
+        if let Some(level) = self.default {
+            for &format in formats.iter().filter(|format| level_range(**format).is_some()) {
+                if self.per_format.iter().any(|(other, _)| *other == format) {
+                    continue;
+                }
+                if let Err(detail) = check_level(level, format) {
+                    let error = FinalError::with_title("Invalid compression level")
+                        .detail(detail)
+                        .hint("Levels can be chosen for each format separately, for example:")
+                        .hint("  --level gz=9,zst=19");
+                    return Err(error.into());
+                }
+            }
+
+            if matches!(level, Level::Exact(_) | Level::Extreme(_))
+                && !formats.iter().any(|format| level_range(*format).is_some())
+            {
+                warning!("None of the formats support compression levels, the level will be ignored.");
+            }
+        }
+
+        for (format, _) in &self.per_format {
+            if !formats.contains(format) {
+                warning!("The format '{}' isn't being used, its compression level will be ignored.", format);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The level chosen for the format, if any
+    pub fn get(&self, format: CompressionFormat) -> Option<Level> {
+        let per_format = self.per_format.iter().find(|(other, _)| *other == format).map(|(_, level)| *level);
+        per_format.or(self.default).filter(|_| level_range(format).is_some())
+    }
+}
+
+/// Parses a format name used as a key in `--level`, like "gz" or "xz"
+fn parse_single_format(text: &str) -> Option<CompressionFormat> {
+    let extension = extension::to_extension(text.strip_prefix('.').unwrap_or(text))?;
+    match extension.compression_formats {
+        [format] if level_range(*format).is_some() => Some(*format),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_levels() {
+        let levels = CompressionLevels::new(Some("9"), false, false).unwrap();
+        assert_eq!(levels.get(Gzip), Some(Level::Exact(9)));
+        assert_eq!(levels.get(Snappy), None);
+
+        let levels = CompressionLevels::new(Some("gz=9,xz=3e"), false, false).unwrap();
+        assert_eq!(levels.get(Gzip), Some(Level::Exact(9)));
+        assert_eq!(levels.get(Xz), Some(Level::Extreme(3)));
+        assert_eq!(levels.get(Zstd), None);
+
+        let levels = CompressionLevels::new(Some("zst=-5"), true, false).unwrap();
+        assert_eq!(levels.get(Zstd), Some(Level::Exact(-5)));
+        assert_eq!(levels.get(Bzip), Some(Level::Fastest));
+
+        assert!(CompressionLevels::new(Some("fast"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("1,2"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("gz=10"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("gz=9e"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("lzma=9e"), false, false).is_ok());
+        assert!(CompressionLevels::new(Some("sz=1"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("tgz=1"), false, false).is_err());
+        assert!(CompressionLevels::new(Some("gz=1,gz=2"), false, false).is_err());
+    }
+
+    #[test]
+    fn test_level_values() {
+        assert_eq!(Level::Fastest.value(Xz), 0);
+        assert_eq!(Level::Fastest.value(Zstd), 1);
+        assert_eq!(Level::Best.value(Lz4), 12);
+        assert_eq!(Level::Best.value(Zstd), 19);
+        assert_eq!(Level::Best.value(Brotli), 11);
+        assert_eq!(brotli_window(0), 16);
+        assert_eq!(brotli_window(BROTLI_DEFAULT_QUALITY), 24);
+        assert_eq!(Level::Exact(6).xz_preset(), 6);
+        assert_eq!(Level::Extreme(9).xz_preset(), 9 | XZ_PRESET_EXTREME);
+    }
+
+    #[test]
+    fn test_validate_levels() {
+        let tar_gz = [Extension::new(&[Tar, Gzip], "tgz")];
+        let tar_zst = [Extension::new(&[Tar, Zstd], "tzst")];
+
+        assert!(CompressionLevels::new(Some("9"), false, false).unwrap().validate(&tar_gz).is_ok());
+        assert!(CompressionLevels::new(Some("15"), false, false).unwrap().validate(&tar_gz).is_err());
+        assert!(CompressionLevels::new(Some("15"), false, false).unwrap().validate(&tar_zst).is_ok());
+        assert!(CompressionLevels::new(Some("-3"), false, false).unwrap().validate(&tar_zst).is_ok());
+        assert!(CompressionLevels::new(Some("15,gz=9"), false, false).unwrap().validate(&tar_gz).is_ok());
+    }
+}
@@ -0,0 +1,446 @@
+//! Encoder and decoder of the .Z format of the Unix `compress` command, which uses LZW
+//!
+//! Codes start with 9 bits and grow up to 16 bits as the table fills, written from the least significant bit.
+//! They're written in groups of 8 codes, and the group is padded whenever the width of the codes changes or the
+//! table is cleared, this layout comes from the original implementation and is kept by every other one.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+/// The magic bytes, followed by a byte with the flags
+const MAGIC: [u8; 2] = [0x1F, 0x9D];
+
+/// Flag of the streams that can clear the table with `CLEAR`, always set by modern implementations
+const BLOCK_MODE: u8 = 0x80;
+
+/// Bits of the flags byte with the maximum width of the codes
+const BITS_MASK: u8 = 0x1F;
+
+/// Width of the first codes
+const INIT_BITS: u32 = 9;
+
+/// Maximum width of the codes, the same as the default of `compress`
+const MAX_BITS: u32 = 16;
+
+/// Code that clears the table in block mode
+const CLEAR: u32 = 256;
+
+/// First code of the table in block mode
+const FIRST: u32 = 257;
+
+/// How often the encoder checks whether the compression ratio dropped, in bytes of input
+const CHECK_GAP: u64 = 10_000;
+
+/// Amount of output buffered by the encoder before writing it
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// The largest code that fits in `n_bits`, the table has `1 << max_bits` codes at most
+fn max_code(n_bits: u32, max_bits: u32) -> u32 {
+    if n_bits == max_bits {
+        1 << max_bits
+    } else {
+        (1 << n_bits) - 1
+    }
+}
+
+/// An encoder that writes .Z streams with codes of up to 16 bits in block mode, like `compress`
+///
+/// Like `flate2::write::GzEncoder`, the stream is finished when the encoder is dropped,
+/// call [`LzwEncoder::finish`] to handle the errors of the last writes.
+pub struct LzwEncoder<W: Write> {
+    writer: Option<W>,
+    /// Codes of the strings in the table, by the code of the string without its last byte and that byte
+    table: HashMap<(u32, u8), u32>,
+    free_ent: u32,
+    n_bits: u32,
+    max_code: u32,
+    /// Code of the longest string in the table that matches the end of the input, not written yet
+    current: Option<u32>,
+    /// Bits that don't fill a byte yet
+    bits: u32,
+    bit_count: u32,
+    codes_in_group: u32,
+    buffer: Vec<u8>,
+    bytes_in: u64,
+    bytes_out: u64,
+    /// Input size of the next check of the compression ratio, once the table is full
+    checkpoint: u64,
+    ratio: u64,
+}
+
+impl<W: Write> LzwEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        let mut buffer = Vec::with_capacity(BUFFER_SIZE);
+        buffer.extend_from_slice(&MAGIC);
+        buffer.push(BLOCK_MODE | MAX_BITS as u8);
+
+        Self {
+            writer: Some(writer),
+            table: HashMap::new(),
+            free_ent: FIRST,
+            n_bits: INIT_BITS,
+            max_code: max_code(INIT_BITS, MAX_BITS),
+            current: None,
+            bits: 0,
+            bit_count: 0,
+            codes_in_group: 0,
+            buffer,
+            bytes_in: 0,
+            bytes_out: 0,
+            checkpoint: CHECK_GAP,
+            ratio: 0,
+        }
+    }
+
+    /// Finishes the .Z stream and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().expect("writer is only taken when finishing"))
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        if let Some(code) = self.current.take() {
+            self.write_code(code);
+        }
+        // The last group isn't padded, only the last byte is
+        if self.bit_count > 0 {
+            self.buffer.push(self.bits as u8);
+            self.bits = 0;
+            self.bit_count = 0;
+        }
+        self.flush()
+    }
+
+    fn write_code(&mut self, code: u32) {
+        self.bits |= code << self.bit_count;
+        self.bit_count += self.n_bits;
+        self.push_bytes();
+        self.codes_in_group = (self.codes_in_group + 1) % 8;
+
+        if self.free_ent > self.max_code {
+            self.pad_group();
+            self.n_bits += 1;
+            self.max_code = max_code(self.n_bits, MAX_BITS);
+        }
+    }
+
+    /// Fills the rest of the group of 8 codes with zeros
+    fn pad_group(&mut self) {
+        if self.codes_in_group > 0 {
+            for _ in self.codes_in_group..8 {
+                self.bit_count += self.n_bits;
+                self.push_bytes();
+            }
+            self.codes_in_group = 0;
+        }
+    }
+
+    /// Moves the complete bytes of `bits` into the buffer
+    fn push_bytes(&mut self) {
+        while self.bit_count >= 8 {
+            self.buffer.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+            self.bytes_out += 1;
+        }
+    }
+
+    /// Clears the table if the compression ratio dropped since the last check, like `compress` does
+    fn check_ratio(&mut self) {
+        self.checkpoint = self.bytes_in + CHECK_GAP;
+        let ratio = (self.bytes_in << 8) / self.bytes_out.max(1);
+
+        if ratio > self.ratio {
+            self.ratio = ratio;
+        } else {
+            self.ratio = 0;
+            self.table.clear();
+            self.free_ent = FIRST;
+            self.write_code(CLEAR);
+            self.pad_group();
+            self.n_bits = INIT_BITS;
+            self.max_code = max_code(INIT_BITS, MAX_BITS);
+        }
+    }
+}
+
+impl<W: Write> Write for LzwEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.bytes_in += 1;
+            let prefix = match self.current {
+                Some(prefix) => prefix,
+                None => {
+                    self.current = Some(byte.into());
+                    continue;
+                }
+            };
+
+            if let Some(&code) = self.table.get(&(prefix, byte)) {
+                self.current = Some(code);
+                continue;
+            }
+
+            self.write_code(prefix);
+            if self.free_ent < 1 << MAX_BITS {
+                self.table.insert((prefix, byte), self.free_ent);
+                self.free_ent += 1;
+            } else if self.bytes_in >= self.checkpoint {
+                self.check_ratio();
+            }
+            self.current = Some(byte.into());
+        }
+
+        if self.buffer.len() >= BUFFER_SIZE {
+            let writer = self.writer.as_mut().expect("writer is present while writing");
+            writer.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(buf.len())
+    }
+
+    /// Writes the complete bytes, the codes that are still being matched are only written when finishing
+    fn flush(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().expect("writer is present while writing");
+        writer.write_all(&self.buffer)?;
+        self.buffer.clear();
+        writer.flush()
+    }
+}
+
+impl<W: Write> Drop for LzwEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A decoder of .Z streams
+pub struct LzwDecoder<R: Read> {
+    reader: BufReader<R>,
+    max_bits: u32,
+    block_mode: bool,
+    /// For each code of the table above 255, the code of the string without its last byte and that byte
+    prefixes: Vec<u16>,
+    suffixes: Vec<u8>,
+    free_ent: u32,
+    n_bits: u32,
+    max_code: u32,
+    /// Bits that weren't used by a code yet
+    bits: u32,
+    bit_count: u32,
+    codes_in_group: u32,
+    old_code: Option<u32>,
+    /// First byte of the string of `old_code`
+    first_byte: u8,
+    /// The decoded string that wasn't read yet, reversed
+    pending: Vec<u8>,
+    finished: bool,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    /// Reads the header of the stream, failing if it isn't a .Z stream
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut header = [0; 3];
+        reader.read_exact(&mut header)?;
+
+        let max_bits = (header[2] & BITS_MASK) as u32;
+        if header[..2] != MAGIC || !(INIT_BITS..=MAX_BITS).contains(&max_bits) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid .Z header"));
+        }
+        let block_mode = header[2] & BLOCK_MODE != 0;
+
+        Ok(Self {
+            reader,
+            max_bits,
+            block_mode,
+            prefixes: vec![0; 1 << MAX_BITS],
+            suffixes: vec![0; 1 << MAX_BITS],
+            free_ent: if block_mode { FIRST } else { CLEAR },
+            n_bits: INIT_BITS,
+            // The first width change happens at 512 codes even when `max_bits` is 9, like in `compress`
+            max_code: max_code(INIT_BITS, MAX_BITS),
+            bits: 0,
+            bit_count: 0,
+            codes_in_group: 0,
+            old_code: None,
+            first_byte: 0,
+            pending: vec![],
+            finished: false,
+        })
+    }
+
+    /// Reads the next code, or `None` at the end of the stream
+    fn read_code(&mut self) -> io::Result<Option<u32>> {
+        while self.bit_count < self.n_bits {
+            match self.next_byte()? {
+                Some(byte) => {
+                    self.bits |= u32::from(byte) << self.bit_count;
+                    self.bit_count += 8;
+                }
+                // Leftover bits of the last byte are padding
+                None => return Ok(None),
+            }
+        }
+
+        let code = self.bits & ((1 << self.n_bits) - 1);
+        self.bits >>= self.n_bits;
+        self.bit_count -= self.n_bits;
+        self.codes_in_group = (self.codes_in_group + 1) % 8;
+        Ok(Some(code))
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.reader.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// Skips the padding at the end of the group of 8 codes
+    fn skip_group_padding(&mut self) -> io::Result<()> {
+        if self.codes_in_group > 0 {
+            let mut padding = (8 - self.codes_in_group) * self.n_bits - self.bit_count;
+            self.bits = 0;
+            self.bit_count = 0;
+            while padding > 0 && self.next_byte()?.is_some() {
+                padding -= 8;
+            }
+            self.codes_in_group = 0;
+        }
+        Ok(())
+    }
+
+    /// Decodes the next string into `pending`, returns `false` at the end of the stream
+    fn decode_next(&mut self) -> io::Result<bool> {
+        loop {
+            if self.free_ent > self.max_code {
+                self.skip_group_padding()?;
+                self.n_bits += 1;
+                self.max_code = max_code(self.n_bits, self.max_bits);
+            }
+
+            let code = match self.read_code()? {
+                Some(code) => code,
+                None => return Ok(false),
+            };
+
+            let old_code = match self.old_code {
+                Some(old_code) => old_code,
+                None => {
+                    if code > u8::MAX.into() {
+                        return Err(corrupted());
+                    }
+                    self.old_code = Some(code);
+                    self.first_byte = code as u8;
+                    self.pending.push(code as u8);
+                    return Ok(true);
+                }
+            };
+
+            if code == CLEAR && self.block_mode {
+                // The next code is added in the place of `CLEAR`, which is never used
+                self.free_ent = CLEAR;
+                self.skip_group_padding()?;
+                self.n_bits = INIT_BITS;
+                self.max_code = max_code(INIT_BITS, MAX_BITS);
+                continue;
+            }
+
+            // The string of a code that isn't in the table yet starts and ends with the first byte of the previous one
+            let mut string_code = code;
+            if code >= self.free_ent {
+                if code > self.free_ent {
+                    return Err(corrupted());
+                }
+                self.pending.push(self.first_byte);
+                string_code = old_code;
+            }
+            while string_code > u8::MAX.into() {
+                self.pending.push(self.suffixes[string_code as usize]);
+                string_code = self.prefixes[string_code as usize].into();
+            }
+            self.first_byte = string_code as u8;
+            self.pending.push(self.first_byte);
+
+            if self.free_ent < 1 << self.max_bits {
+                self.prefixes[self.free_ent as usize] = old_code as u16;
+                self.suffixes[self.free_ent as usize] = self.first_byte;
+                self.free_ent += 1;
+            }
+            self.old_code = Some(code);
+            return Ok(true);
+        }
+    }
+}
+
+fn corrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupted .Z data")
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.pending.pop() {
+                Some(byte) => {
+                    buf[read] = byte;
+                    read += 1;
+                }
+                None if self.finished => break,
+                None => self.finished = !self.decode_next()?,
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, RngCore, SeedableRng};
+
+    use super::*;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = LzwEncoder::new(vec![]);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let mut decompressed = vec![];
+        LzwDecoder::new(data).unwrap().read_to_end(&mut decompressed).unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn test_lzw_decode_compress_output() {
+        // "TOBEORNOTTOBEORTOBEORNOT" in the .Z format
+        let compressed = [
+            0x1F, 0x9D, 0x90, 0x54, 0x9E, 0x08, 0x29, 0xF2, 0x44, 0x8A, 0x93, 0x27, 0x54, 0x02, 0x0E, 0x2C, 0xA8, 0x90,
+            0xA0, 0x41, 0x84,
+        ];
+        assert_eq!(decompress(&compressed), b"TOBEORNOTTOBEORTOBEORNOT");
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        // Repetitive text makes the codes grow up to 16 bits, random data makes the table be cleared
+        let text: Vec<u8> = (0..200_000u32).flat_map(|i| format!("line {}\n", i % 7919).into_bytes()).collect();
+        let mut random = vec![0; 300_000];
+        SmallRng::seed_from_u64(0).fill_bytes(&mut random);
+
+        for data in [&[][..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", &text, &random] {
+            assert_eq!(decompress(&compress(data)), data);
+        }
+    }
+}
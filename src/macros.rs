@@ -15,15 +15,14 @@
 ///   who have to have each line of output read to them aloud, whithout to
 ///   ability to skip some lines deemed not important like a seeing person would.
 ///
-/// By default `info` outputs to Stdout, if you want to specify the output you can use
-/// `@display_handle` modifier
-
+/// By default `info` outputs to Stdout (or Stderr, if Stdout is carrying data), if you want
+/// to specify the output you can use `@display_handle` modifier
 #[macro_export]
 macro_rules! info {
     // Accessible (short/important) info message.
     // Show info message even in ACCESSIBLE mode
     (accessible, $($arg:tt)*) => {
-        info!(@::std::io::stdout(), accessible, $($arg)*);
+        info!(@$crate::utils::message_output(), accessible, $($arg)*);
     };
     (@$display_handle: expr, accessible, $($arg:tt)*) => {
         let display_handle = &mut $display_handle;
@@ -36,7 +35,7 @@ macro_rules! info {
     // Inccessible (long/no important) info message.
     // Print info message if ACCESSIBLE is not turned on
     (inaccessible, $($arg:tt)*) => {
-        info!(@::std::io::stdout(), inaccessible, $($arg)*);
+        info!(@$crate::utils::message_output(), inaccessible, $($arg)*);
     };
     (@$display_handle: expr, inaccessible, $($arg:tt)*) => {
         if (!$crate::cli::ACCESSIBLE.get().unwrap())
@@ -69,8 +68,8 @@ pub fn _warning_helper() {
     use crate::utils::colors::{ORANGE, RESET};
 
     if !crate::cli::ACCESSIBLE.get().unwrap() {
-        print!("{}Warning:{} ", *ORANGE, *RESET);
+        eprint!("{}Warning:{} ", *ORANGE, *RESET);
     } else {
-        print!("{}[WARNING]{} ", *ORANGE, *RESET);
+        eprint!("{}[WARNING]{} ", *ORANGE, *RESET);
     }
 }
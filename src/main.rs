@@ -6,9 +6,14 @@ pub mod cli;
 pub mod commands;
 pub mod error;
 pub mod extension;
+pub mod integrity;
+pub mod level;
 pub mod list;
+pub mod lzw;
+pub mod parallel_gzip;
 pub mod progress;
 pub mod utils;
+pub mod zstd_seekable;
 
 /// CLI argparsing definitions, using `clap`.
 pub mod opts;
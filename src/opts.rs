@@ -1,11 +1,11 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, num::NonZeroUsize, path::PathBuf};
 
 use clap::{Parser, ValueHint};
 
 // Command line options
 /// A command-line utility for easily compressing and decompressing files and directories.
 ///
-/// Supported formats: tar, zip, bz/bz2, gz, lz4, xz/lz/lzma, zst.
+/// Supported formats: tar, zip, 7z, rar (decompression only), cpio, ar/deb, bz/bz2, gz, lz4, xz, lzma, lz, br, Z, zst.
 ///
 /// Repository: https://github.com/ouch-org/ouch
 #[derive(Parser, Debug)]
@@ -44,6 +44,10 @@ pub struct Opts {
 // - `compress`
 // - `decompress`
 // - `list`
+// - `cat`
+// - `test`
+// - `convert`
+// - `zstd-train`
 //
 // Clap commands:
 //  - `help`
@@ -52,34 +56,228 @@ pub enum Subcommand {
     /// Compress one or more files into one output file.
     #[clap(alias = "c")]
     Compress {
-        /// Files to be compressed.
+        /// Files to be compressed, '-' reads from stdin.
         #[clap(required = true, min_values = 1)]
         files: Vec<PathBuf>,
 
-        /// The resulting file. Its extensions can be used to specify the compression formats.
+        /// The resulting file. Its extensions can be used to specify the compression formats, '-' writes to stdout.
         #[clap(required = true, value_hint = ValueHint::FilePath)]
         output: PathBuf,
+
+        /// Specify the compression formats instead of detecting them from the output extension, e.g. "tar.gz".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Compression level for all formats, e.g. "9", or for each format, e.g. "gz=9,xz=9e,zst=-3".
+        #[clap(short, long, allow_hyphen_values = true)]
+        level: Option<String>,
+
+        /// Use the fastest compression level of each format.
+        #[clap(long, conflicts_with_all = &["level", "best"])]
+        fast: bool,
+
+        /// Use the compression level of each format that produces the smallest output.
+        #[clap(long, conflicts_with = "level")]
+        best: bool,
+
+        /// Number of threads used by the formats that support multithreading (gzip, zstd, xz and lzip), defaults to the number of cores.
+        #[clap(short = 'T', long)]
+        threads: Option<NonZeroUsize>,
+
+        /// How the files of zip archives are compressed, "auto" stores the ones that are already compressed and deflates the rest.
+        #[clap(long, arg_enum, default_value = "auto")]
+        zip_method: ZipMethod,
+
+        /// Encrypt the files of zip archives with this password, using AES-256.
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// Compress with a zstd dictionary, trained with 'ouch zstd-train'.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+
+        /// Enable zstd long distance matching, with a window of 2^WINDOW_LOG bytes (27 by default, up to 31).
+        #[clap(
+            long,
+            value_name = "WINDOW_LOG",
+            min_values = 0,
+            max_values = 1,
+            require_equals = true,
+            default_missing_value = "27"
+        )]
+        zstd_long: Option<u32>,
+
+        /// Compress into the seekable format of zstd, so the files of .tar.zst archives can be read without decompressing the ones before them.
+        #[clap(long, conflicts_with = "zstd-long")]
+        zstd_seekable: bool,
     },
     /// Decompresses one or more files, optionally into another folder.
     #[clap(alias = "d")]
     Decompress {
-        /// Files to be decompressed.
+        /// Files to be decompressed, '-' reads from stdin (single file formats are then written to stdout).
         #[clap(required = true, min_values = 1)]
         files: Vec<PathBuf>,
 
         /// Choose to  files in a directory other than the current
         #[clap(short = 'd', long = "dir", value_hint = ValueHint::DirPath)]
         output_dir: Option<PathBuf>,
+
+        /// Specify the formats of the files instead of detecting them from their extensions, e.g. "tar.gz".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Password of encrypted zip archives, it's asked if needed and not given.
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// The zstd dictionary the files were compressed with.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+
+        /// Only unpack the members of archives with these paths or globs, e.g. 'src/**/*.rs', given after '--'.
+        #[clap(last = true)]
+        members: Vec<String>,
+
+        /// Don't unpack the members of archives matching this path or glob, can be given several times.
+        #[clap(long, multiple_occurrences = true)]
+        exclude: Vec<String>,
+
+        /// Remove this number of leading directories from the paths of the members of archives, the members with nothing left are skipped.
+        #[clap(long, value_name = "N", default_value = "0")]
+        strip_components: usize,
+
+        /// Rewrite the paths of the members of archives with a sed-like substitution, e.g. 's/^project-1.2.3/project/'.
+        #[clap(long, value_name = "EXPRESSION")]
+        transform: Option<String>,
     },
     /// List contents.     Alias: l
     #[clap(alias = "l")]
     List {
-        /// Archives whose contents should be listed
+        /// Archives whose contents should be listed, '-' reads from stdin
         #[clap(required = true, min_values = 1)]
         archives: Vec<PathBuf>,
 
         /// Show archive contents as a tree
         #[clap(short, long)]
         tree: bool,
+
+        /// Specify the formats of the archives instead of detecting them from their extensions, e.g. "tar.gz".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Password of encrypted zip and rar archives, it's asked if needed and not given.
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// The zstd dictionary the archives were compressed with.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
     },
+    /// Write a file of an archive, or the decompressed contents of a single file format, to stdout.
+    Cat {
+        /// The archive or compressed file, '-' reads from stdin
+        #[clap(value_hint = ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Path of the file inside of the archive, as shown by 'ouch list'. Not given for single file formats.
+        member: Option<PathBuf>,
+
+        /// Specify the format of the archive instead of detecting it from its extension, e.g. "tar.gz".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Password of encrypted zip and rar archives, it's asked if needed and not given.
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// The zstd dictionary the archive was compressed with.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+    },
+    /// Test the integrity of archives and compressed files, verifying their checksums without writing anything.
+    Test {
+        /// Archives and compressed files to be tested, '-' reads from stdin
+        #[clap(required = true, min_values = 1)]
+        archives: Vec<PathBuf>,
+
+        /// Specify the formats of the archives instead of detecting them from their extensions, e.g. "tar.gz".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Password of encrypted zip and rar archives, it's asked if needed and not given.
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// The zstd dictionary the archives were compressed with.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        zstd_dict: Option<PathBuf>,
+    },
+    /// Convert an archive or a compressed file into other formats, without decompressing it to the disk.
+    Convert {
+        /// The archive or compressed file to convert, '-' reads from stdin
+        #[clap(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+
+        /// The resulting file. Its extensions specify the formats to convert to, '-' writes to stdout.
+        #[clap(value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Specify the formats to convert to instead of detecting them from the output extension, e.g. "tar.zst".
+        #[clap(long)]
+        format: Option<OsString>,
+
+        /// Specify the formats of the input instead of detecting them from its extension, e.g. "tar.gz".
+        #[clap(long)]
+        input_format: Option<OsString>,
+
+        /// Compression level for all formats, e.g. "9", or for each format, e.g. "gz=9,xz=9e,zst=-3".
+        #[clap(short, long, allow_hyphen_values = true)]
+        level: Option<String>,
+
+        /// Use the fastest compression level of each format.
+        #[clap(long, conflicts_with_all = &["level", "best"])]
+        fast: bool,
+
+        /// Use the compression level of each format that produces the smallest output.
+        #[clap(long, conflicts_with = "level")]
+        best: bool,
+
+        /// Number of threads used by the formats that support multithreading (gzip, zstd, xz and lzip), defaults to the number of cores.
+        #[clap(short = 'T', long)]
+        threads: Option<NonZeroUsize>,
+
+        /// How the files of zip archives are compressed, "auto" stores the ones that are already compressed and deflates the rest.
+        #[clap(long, arg_enum, default_value = "auto")]
+        zip_method: ZipMethod,
+
+        /// Password of encrypted zip input archives, it's asked if needed and not given.
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Train a zstd dictionary on sample files, which improves the compression of small files with '--zstd-dict'.
+    ZstdTrain {
+        /// Sample files, the files inside of directories are used too.
+        #[clap(required = true, min_values = 1)]
+        samples: Vec<PathBuf>,
+
+        /// The resulting dictionary.
+        #[clap(short, long, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Maximum size of the dictionary in bytes, the default is the same as zstd's.
+        #[clap(long, default_value = "112640")]
+        max_size: usize,
+    },
+}
+
+/// Compression method of the files inside of zip archives
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ZipMethod {
+    /// Store files that are already compressed, deflate the rest
+    Auto,
+    /// No compression
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
 }
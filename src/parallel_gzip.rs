@@ -0,0 +1,277 @@
+//! Gzip encoder that deflates blocks of the input on several threads, like `pigz`
+//!
+//! The input is split into blocks, each block is deflated on its own thread with the end of the
+//! previous block as the dictionary, and the results are concatenated into a single gzip stream,
+//! which can be decompressed by any gzip decoder.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use flate2::{Compress, Compression, Crc, FlushCompress, Status};
+
+/// Amount of input deflated by each job, the same as pigz's default
+const BLOCK_SIZE: usize = 128 * 1024;
+
+/// The deflate window, the largest distance that can be referenced by a block
+const DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// A gzip encoder that compresses the data in parallel
+///
+/// Like `flate2::write::GzEncoder`, the stream is finished when the encoder is dropped,
+/// call [`ParallelGzEncoder::finish`] to handle the errors of the last writes.
+pub struct ParallelGzEncoder<W: Write> {
+    writer: Option<W>,
+    level: Compression,
+    pool: ThreadPool,
+    /// Input that wasn't sent to the threads yet
+    block: Vec<u8>,
+    /// The last `DICTIONARY_SIZE` bytes that were sent to the threads
+    dictionary: Vec<u8>,
+    /// Blocks being deflated, in the same order as the input
+    pending: VecDeque<mpsc::Receiver<io::Result<Vec<u8>>>>,
+    crc: Crc,
+    header_written: bool,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    /// Creates an encoder that deflates blocks on `threads` threads
+    pub fn new(writer: W, level: Compression, threads: usize) -> Self {
+        Self {
+            writer: Some(writer),
+            level,
+            pool: ThreadPool::new(threads),
+            block: Vec::with_capacity(BLOCK_SIZE),
+            dictionary: Vec::with_capacity(DICTIONARY_SIZE),
+            pending: VecDeque::new(),
+            crc: Crc::new(),
+            header_written: false,
+        }
+    }
+
+    /// Finishes the gzip stream and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().expect("writer is only taken when finishing"))
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        self.send_block(true);
+        while !self.pending.is_empty() {
+            self.write_next_block()?;
+        }
+
+        let writer = self.writer.as_mut().expect("checked above");
+        writer.write_all(&self.crc.sum().to_le_bytes())?;
+        writer.write_all(&self.crc.amount().to_le_bytes())?;
+        writer.flush()
+    }
+
+    /// Sends the buffered input to be deflated, the last block finishes the deflate stream
+    fn send_block(&mut self, last: bool) {
+        let block = std::mem::replace(&mut self.block, Vec::with_capacity(BLOCK_SIZE));
+        let dictionary = self.dictionary.clone();
+        let level = self.level;
+
+        self.dictionary.extend_from_slice(&block[block.len().saturating_sub(DICTIONARY_SIZE)..]);
+        let excess = self.dictionary.len().saturating_sub(DICTIONARY_SIZE);
+        self.dictionary.drain(..excess);
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.pool.execute(move || {
+            // The receiver is only dropped if the encoder is dropped, the result isn't needed anymore then
+            let _ = sender.send(deflate_block(&block, &dictionary, level, last));
+        });
+        self.pending.push_back(receiver);
+    }
+
+    /// Waits for the oldest block being deflated and writes it
+    fn write_next_block(&mut self) -> io::Result<()> {
+        let receiver = self.pending.pop_front().expect("there is a pending block");
+        let deflated = receiver.recv().map_err(|_| io::Error::other("gzip compression thread panicked"))??;
+
+        let writer = self.writer.as_mut().expect("writer is present while writing");
+        if !self.header_written {
+            writer.write_all(&gzip_header(self.level))?;
+            self.header_written = true;
+        }
+        writer.write_all(&deflated)
+    }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full block is only sent once more input arrives, so the last block is always sent by `try_finish`
+        if self.block.len() == BLOCK_SIZE {
+            self.send_block(false);
+            // Bound the memory used by the blocks waiting to be written
+            while self.pending.len() > self.pool.size() * 2 {
+                self.write_next_block()?;
+            }
+        }
+
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.crc.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.block.is_empty() {
+            self.send_block(false);
+        }
+        while !self.pending.is_empty() {
+            self.write_next_block()?;
+        }
+        self.writer.as_mut().expect("writer is present while writing").flush()
+    }
+}
+
+impl<W: Write> Drop for ParallelGzEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Deflates a block into raw deflate data, ending at a byte boundary so blocks can be concatenated
+fn deflate_block(block: &[u8], dictionary: &[u8], level: Compression, last: bool) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    if !dictionary.is_empty() {
+        compress.set_dictionary(dictionary).map_err(io::Error::other)?;
+    }
+
+    // A sync flush ends the deflate data at a byte boundary without marking it as the final block
+    let flush = if last { FlushCompress::Finish } else { FlushCompress::Sync };
+    let mut output = Vec::with_capacity(block.len() + block.len() / 8 + 64);
+
+    loop {
+        let input = &block[compress.total_in() as usize..];
+        let status = compress.compress_vec(input, &mut output, flush).map_err(io::Error::other)?;
+
+        let done = match status {
+            Status::StreamEnd => true,
+            // When the output still has room left, the sync flush is complete
+            _ => !last && input.is_empty() && output.len() < output.capacity(),
+        };
+        if done {
+            return Ok(output);
+        }
+        output.reserve(output.capacity().max(1024));
+    }
+}
+
+/// The gzip header written by `flate2::write::GzEncoder`, without a file name or a modification time
+fn gzip_header(level: Compression) -> [u8; 10] {
+    let extra_flags = if level.level() >= Compression::best().level() {
+        2
+    } else if level.level() <= Compression::fast().level() {
+        4
+    } else {
+        0
+    };
+
+    // Magic bytes, deflate method, no flags, no modification time, extra flags, unknown OS
+    [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, extra_flags, 255]
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed amount of threads that run the jobs sent to them
+struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || {
+                    loop {
+                        // The lock is released before running the job
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            // The pool was dropped
+                            Err(_) => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self { sender: Some(sender), workers }
+    }
+
+    fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        // Workers only stop after the sender is dropped
+        self.sender.as_ref().unwrap().send(Box::new(job)).unwrap();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn compress(data: &[u8], threads: usize, flush_every: Option<usize>) -> Vec<u8> {
+        let mut encoder = ParallelGzEncoder::new(vec![], Compression::default(), threads);
+        for chunk in data.chunks(flush_every.unwrap_or(usize::MAX)) {
+            encoder.write_all(chunk).unwrap();
+            if flush_every.is_some() {
+                encoder.flush().unwrap();
+            }
+        }
+        encoder.finish().unwrap()
+    }
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let mut decompressed = vec![];
+        let mut decoder = flate2::read::GzDecoder::new(data);
+        decoder.read_to_end(&mut decompressed).unwrap();
+        // A single gzip member, nothing is left after it
+        assert!(decoder.into_inner().is_empty());
+        decompressed
+    }
+
+    #[test]
+    fn test_parallel_gzip_round_trip() {
+        // Repetitive data, so blocks reference the dictionary of the previous block
+        let text: Vec<u8> = (0..200_000u32).flat_map(|i| format!("line {}\n", i % 7919).into_bytes()).collect();
+
+        for len in [0, 1, DICTIONARY_SIZE, BLOCK_SIZE, BLOCK_SIZE * 3, BLOCK_SIZE * 3 + 1, text.len()] {
+            let data = &text[..len];
+            for threads in [1, 2, 5] {
+                assert_eq!(decompress(&compress(data, threads, None)), data);
+            }
+            assert_eq!(decompress(&compress(data, 3, Some(10_000))), data);
+        }
+    }
+}
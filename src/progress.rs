@@ -34,7 +34,7 @@ impl io::Write for DisplayHandle {
 
     fn flush(&mut self) -> io::Result<()> {
         fn io_error<X>(_: X) -> io::Error {
-            io::Error::new(io::ErrorKind::Other, "failed to flush buffer")
+            io::Error::other("failed to flush buffer")
         }
         self.sender.send(String::from_utf8(self.buf.drain(..).collect()).map_err(io_error)?).map_err(io_error)
     }
@@ -60,14 +60,42 @@ pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
     Ok(previous_location)
 }
 
+/// How many bytes from the start of a file are needed to detect its format by its magic strings
+pub const MAGIC_BYTES_LEN: usize = 270;
+
 /// Try to detect the file extension by looking for known magic strings
 /// Source: <https://en.wikipedia.org/wiki/List_of_file_signatures>
 pub fn try_infer_extension(path: &Path) -> Option<Extension> {
+    let mut buf = Vec::with_capacity(MAGIC_BYTES_LEN);
+
+    // Error cause will be ignored, so use std::fs instead of fs_err
+    let result = std::fs::File::open(path).and_then(|file| file.take(MAGIC_BYTES_LEN as u64).read_to_end(&mut buf));
+
+    // In case of file open or read failure, could not infer a extension
+    if result.is_err() {
+        return None;
+    }
+
+    // Only the bytes that were read, short brotli files followed by zeroes wouldn't be valid
+    try_infer_extension_from_bytes(&buf)
+}
+
+/// Try to detect the extension of the data starting with `buf` by looking for known magic strings
+///
+/// See [`try_infer_extension`], this is useful for data that doesn't come from a file, like stdin.
+pub fn try_infer_extension_from_bytes(buf: &[u8]) -> Option<Extension> {
     fn is_zip(buf: &[u8]) -> bool {
-        buf.len() >= 3
+        buf.len() >= 4
             && buf[..=1] == [0x50, 0x4B]
             && (buf[2..=3] == [0x3, 0x4] || buf[2..=3] == [0x5, 0x6] || buf[2..=3] == [0x7, 0x8])
     }
+    fn is_7z(buf: &[u8]) -> bool {
+        buf.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C])
+    }
+    fn is_rar(buf: &[u8]) -> bool {
+        // Both RAR4 and RAR5 signatures start with these bytes
+        buf.starts_with(&[0x52, 0x61, 0x72, 0x21, 0x1A, 0x07])
+    }
     fn is_tar(buf: &[u8]) -> bool {
         buf.len() > 261 && buf[257..=261] == [0x75, 0x73, 0x74, 0x61, 0x72]
     }
@@ -80,46 +108,107 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
     fn is_xz(buf: &[u8]) -> bool {
         buf.starts_with(&[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00])
     }
+    fn is_lzma(buf: &[u8]) -> bool {
+        // LZMA-alone files have no magic bytes, so the header is checked the same way liblzma does: a valid
+        // lc/lp/pb byte, a dictionary size of 2^n or 2^n + 2^(n-1), and a size that is unknown or below 256 GiB
+        if buf.len() < 13 {
+            return false;
+        }
+        let dict_size = u32::from_le_bytes(buf[1..5].try_into().unwrap());
+        let uncompressed_size = u64::from_le_bytes(buf[5..13].try_into().unwrap());
+
+        let valid_dict_size = dict_size == u32::MAX || {
+            let rounded = dict_size.checked_next_power_of_two().unwrap_or(u32::MAX);
+            dict_size == rounded || dict_size == rounded - rounded / 4
+        };
+        buf[0] < 225 && valid_dict_size && (uncompressed_size == u64::MAX || uncompressed_size < 1 << 38)
+    }
+    fn is_br(buf: &[u8]) -> bool {
+        use brotli::{enc::StandardAlloc, BrotliDecompressStream, BrotliResult, BrotliState};
+
+        // Brotli has no magic bytes either, so the start of the data is decoded and checked for errors.
+        // Most data is also valid inside of uncompressed or metadata blocks, which encoders don't start with
+        let mut state = BrotliState::new(StandardAlloc::default(), StandardAlloc::default(), StandardAlloc::default());
+        let mut output = [0; 4096];
+        let (mut available_in, mut input_offset, mut total_out) = (buf.len(), 0, 0);
+        loop {
+            let (mut available_out, mut output_offset) = (output.len(), 0);
+            let result = BrotliDecompressStream(
+                &mut available_in,
+                &mut input_offset,
+                buf,
+                &mut available_out,
+                &mut output_offset,
+                &mut output,
+                &mut total_out,
+                &mut state,
+            );
+            match result {
+                BrotliResult::NeedsMoreOutput => continue,
+                // Only the start of the data is given, shorter data must be a whole brotli stream
+                BrotliResult::NeedsMoreInput => {
+                    return buf.len() >= MAGIC_BYTES_LEN && state.is_uncompressed == 0 && state.is_metadata == 0;
+                }
+                BrotliResult::ResultSuccess => return available_in == 0,
+                BrotliResult::ResultFailure => return false,
+            }
+        }
+    }
+    fn is_cpio(buf: &[u8]) -> bool {
+        [b"070701", b"070702", b"070707"].iter().any(|magic| buf.starts_with(*magic))
+    }
+    fn is_ar(buf: &[u8]) -> bool {
+        buf.starts_with(b"!<arch>\n")
+    }
+    fn is_lz(buf: &[u8]) -> bool {
+        buf.starts_with(&[0x4C, 0x5A, 0x49, 0x50])
+    }
     fn is_lz4(buf: &[u8]) -> bool {
         buf.starts_with(&[0x04, 0x22, 0x4D, 0x18])
     }
     fn is_sz(buf: &[u8]) -> bool {
         buf.starts_with(&[0xFF, 0x06, 0x00, 0x00, 0x73, 0x4E, 0x61, 0x50, 0x70, 0x59])
     }
+    fn is_z(buf: &[u8]) -> bool {
+        buf.starts_with(&[0x1F, 0x9D])
+    }
     fn is_zst(buf: &[u8]) -> bool {
         buf.starts_with(&[0x28, 0xB5, 0x2F, 0xFD])
     }
 
-    let buf = {
-        let mut buf = [0; 270];
-
-        // Error cause will be ignored, so use std::fs instead of fs_err
-        let result = std::fs::File::open(&path).map(|mut file| file.read(&mut buf));
-
-        // In case of file open or read failure, could not infer a extension
-        if result.is_err() {
-            return None;
-        }
-        buf
-    };
-
     use crate::extension::CompressionFormat::*;
-    if is_zip(&buf) {
+    if is_zip(buf) {
         Some(Extension::new(&[Zip], "zip"))
-    } else if is_tar(&buf) {
+    } else if is_7z(buf) {
+        Some(Extension::new(&[SevenZip], "7z"))
+    } else if is_rar(buf) {
+        Some(Extension::new(&[Rar], "rar"))
+    } else if is_tar(buf) {
         Some(Extension::new(&[Tar], "tar"))
-    } else if is_gz(&buf) {
+    } else if is_cpio(buf) {
+        Some(Extension::new(&[Cpio], "cpio"))
+    } else if is_ar(buf) {
+        Some(Extension::new(&[Ar], "ar"))
+    } else if is_gz(buf) {
         Some(Extension::new(&[Gzip], "gz"))
-    } else if is_bz2(&buf) {
+    } else if is_bz2(buf) {
         Some(Extension::new(&[Bzip], "bz2"))
-    } else if is_xz(&buf) {
-        Some(Extension::new(&[Lzma], "xz"))
-    } else if is_lz4(&buf) {
+    } else if is_xz(buf) {
+        Some(Extension::new(&[Xz], "xz"))
+    } else if is_lz(buf) {
+        Some(Extension::new(&[Lzip], "lz"))
+    } else if is_lz4(buf) {
         Some(Extension::new(&[Lz4], "lz4"))
-    } else if is_sz(&buf) {
+    } else if is_sz(buf) {
         Some(Extension::new(&[Snappy], "sz"))
-    } else if is_zst(&buf) {
+    } else if is_zst(buf) {
         Some(Extension::new(&[Zstd], "zst"))
+    } else if is_z(buf) {
+        Some(Extension::new(&[Lzw], "Z"))
+    } else if is_lzma(buf) {
+        Some(Extension::new(&[LzmaAlone], "lzma"))
+    } else if is_br(buf) {
+        Some(Extension::new(&[Brotli], "br"))
     } else {
         None
     }
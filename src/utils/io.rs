@@ -0,0 +1,86 @@
+//! Utils related to stdin and stdout, which are represented by `-` in place of a path.
+
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use fs_err as fs;
+
+/// Checks if `path` is `-`, which means that stdin or stdout should be used instead of a file.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Opens the file at `path` for reading, or stdin if `path` is `-`.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+}
+
+/// Reads up to `len` bytes from the start of `reader`.
+///
+/// Returns the bytes read and a reader that still yields the whole input, useful for inspecting
+/// streams that can't be rewinded, like stdin.
+pub fn peek(mut reader: Box<dyn Read + Send>, len: usize) -> io::Result<(Vec<u8>, Box<dyn Read + Send>)> {
+    let mut prefix = Vec::with_capacity(len);
+    (&mut reader).take(len as u64).read_to_end(&mut prefix)?;
+
+    let reader = io::Cursor::new(prefix.clone()).chain(reader);
+    Ok((prefix, Box::new(reader)))
+}
+
+/// Reads up to `len` bytes from the start of the data decoded from `reader` by `decode`.
+///
+/// Returns the bytes read and a reader that still yields the whole input of the decoder, the bytes the decoder
+/// consumed are kept in memory until they're read again.
+pub fn peek_decoded(
+    reader: Box<dyn Read + Send>,
+    len: usize,
+    decode: impl FnOnce(Box<dyn Read + Send>) -> crate::Result<Box<dyn Read + Send>>,
+) -> (crate::Result<Vec<u8>>, Box<dyn Read + Send>) {
+    /// The reader given to the decoder and the bytes read from it
+    struct Recorded {
+        reader: Box<dyn Read + Send>,
+        bytes: Vec<u8>,
+    }
+
+    /// Reader that keeps a copy of the bytes read from the shared reader
+    struct Recorder(Arc<Mutex<Recorded>>);
+
+    impl Read for Recorder {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let recorded = &mut *self.0.lock().unwrap();
+            let read = recorded.reader.read(buf)?;
+            recorded.bytes.extend_from_slice(&buf[..read]);
+            Ok(read)
+        }
+    }
+
+    let shared = Arc::new(Mutex::new(Recorded { reader, bytes: vec![] }));
+    let prefix = decode(Box::new(Recorder(Arc::clone(&shared)))).and_then(|decoder| {
+        let mut prefix = Vec::with_capacity(len);
+        decoder.take(len as u64).read_to_end(&mut prefix)?;
+        Ok(prefix)
+    });
+
+    // The decoder was dropped, so this is the last reference
+    let Recorded { reader, bytes } = Arc::try_unwrap(shared).ok().unwrap().into_inner().unwrap();
+    (prefix, Box::new(io::Cursor::new(bytes).chain(reader)))
+}
+
+/// Where messages should be written to.
+///
+/// This is stdout, unless stdout is carrying data (e.g. `ouch compress file -`), in which case
+/// messages are written to stderr to not corrupt the output.
+pub fn message_output() -> Box<dyn Write> {
+    if *crate::cli::STDOUT_IS_DATA.get().unwrap_or(&false) {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    }
+}
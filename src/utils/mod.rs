@@ -7,15 +7,19 @@ pub mod colors;
 mod file_visibility;
 mod formatting;
 mod fs;
+mod io;
 mod question;
 
 pub use file_visibility::FileVisibilityPolicy;
 pub use formatting::{concatenate_os_str_list, nice_directory_display, strip_cur_dir, to_utf, Bytes};
 pub use fs::{
     cd_into_same_dir_as, clear_path, create_dir_if_non_existent, dir_is_empty, is_symlink, try_infer_extension,
+    try_infer_extension_from_bytes, MAGIC_BYTES_LEN,
 };
+pub use io::{is_stdio, message_output, open_input, peek, peek_decoded};
 pub use question::{
-    create_or_ask_overwrite, user_wants_to_continue, user_wants_to_overwrite, QuestionAction, QuestionPolicy,
+    ask_password, create_or_ask_overwrite, user_wants_to_continue, user_wants_to_overwrite, QuestionAction,
+    QuestionPolicy,
 };
 pub use utf8::{get_invalid_utf8_paths, is_invalid_utf8};
 
@@ -29,6 +33,6 @@ mod utf8 {
 
     /// Filter out list of paths that are not utf8 valid
     pub fn get_invalid_utf8_paths(paths: &[PathBuf]) -> Vec<&PathBuf> {
-        paths.iter().filter_map(|path| is_invalid_utf8(path).then(|| path)).collect()
+        paths.iter().filter(|path| is_invalid_utf8(path)).collect()
     }
 }
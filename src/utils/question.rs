@@ -11,9 +11,9 @@ use std::{
 
 use fs_err as fs;
 
-use super::{strip_cur_dir, to_utf};
+use super::{message_output, strip_cur_dir, to_utf};
 use crate::{
-    error::{Error, Result},
+    error::{Error, FinalError, Result},
     utils::colors,
 };
 
@@ -94,6 +94,20 @@ pub fn user_wants_to_continue(
     }
 }
 
+/// Asks the user for the password of `path`, which is read from the terminal without being echoed
+pub fn ask_password(path: &Path) -> crate::Result<String> {
+    let mut output = message_output();
+    write!(output, "Enter the password of '{}': ", to_utf(path))?;
+    output.flush()?;
+
+    rpassword::read_password().map_err(|err| {
+        FinalError::with_title(format!("Cannot ask for the password of '{}'", to_utf(path)))
+            .detail(err.to_string())
+            .hint("Use --password to give the password beforehand")
+            .into()
+    })
+}
+
 /// Confirmation dialog for end user with [Y/n] question.
 ///
 /// If the placeholder is found in the prompt text, it will be replaced to form the final message.
@@ -121,14 +135,40 @@ impl<'a> Confirmation<'a> {
             (Some(placeholder), Some(subs)) => Cow::Owned(self.prompt.replace(placeholder, subs)),
         };
 
+        // The answer would be read from stdin, consuming the data being read from it
+        if *crate::cli::STDIN_IS_DATA.get().unwrap_or(&false) {
+            let error = FinalError::with_title(message.into_owned())
+                .detail("Cannot ask questions while reading data from stdin")
+                .hint("Use --yes or --no to answer questions beforehand");
+            return Err(error.into());
+        }
+
+        let mut output = message_output();
+
         // Ask the same question to end while no valid answers are given
         loop {
             if *crate::cli::ACCESSIBLE.get().unwrap() {
-                print!("{} {}yes{}/{}no{}: ", message, *colors::GREEN, *colors::RESET, *colors::RED, *colors::RESET);
+                write!(
+                    output,
+                    "{} {}yes{}/{}no{}: ",
+                    message,
+                    *colors::GREEN,
+                    *colors::RESET,
+                    *colors::RED,
+                    *colors::RESET
+                )?;
             } else {
-                print!("{} [{}Y{}/{}n{}] ", message, *colors::GREEN, *colors::RESET, *colors::RED, *colors::RESET);
+                write!(
+                    output,
+                    "{} [{}Y{}/{}n{}] ",
+                    message,
+                    *colors::GREEN,
+                    *colors::RESET,
+                    *colors::RED,
+                    *colors::RESET
+                )?;
             }
-            io::stdout().flush()?;
+            output.flush()?;
 
             let mut answer = String::new();
             io::stdin().read_line(&mut answer)?;
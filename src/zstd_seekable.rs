@@ -0,0 +1,348 @@
+//! Encoder and decoder of the seekable format of zstd, which makes random access into compressed files possible
+//!
+//! The data is compressed in independent frames, followed by a seek table with the compressed and decompressed size
+//! of each frame. The table is stored in a skippable frame, so any zstd decoder can still decompress the whole file.
+//! The format is specified in the `contrib/seekable_format` directory of the zstd repository.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use zstd::bulk::{Compressor, Decompressor};
+
+/// Amount of input compressed into each frame, the smallest amount decompressed to read anything from the file
+const FRAME_SIZE: usize = 2 * 1024 * 1024;
+
+/// Largest decompressed size of the frames read, the same limit as the reference implementation
+const MAX_FRAME_DECOMPRESSED_SIZE: u32 = 1024 * 1024 * 1024;
+
+/// Magic number of the skippable frame holding the seek table
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+
+/// Magic number at the very end of seekable files
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+/// Size of the skippable frame header, its magic number and the size of its content
+const SKIPPABLE_HEADER_SIZE: u64 = 8;
+
+/// Size of the seek table footer, the number of frames, the descriptor and the magic number
+const FOOTER_SIZE: u64 = 9;
+
+/// Flag of the descriptor set when the entries of the seek table have a checksum
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Bits of the descriptor that must be zero
+const RESERVED_BITS: u8 = 0x7C;
+
+/// An encoder that compresses the input in independent frames of `FRAME_SIZE` bytes and appends the seek table
+///
+/// Like `flate2::write::GzEncoder`, the stream is finished when the encoder is dropped,
+/// call [`SeekableZstdEncoder::finish`] to handle the errors of the last writes.
+pub struct SeekableZstdEncoder<W: Write> {
+    writer: Option<W>,
+    compressor: Compressor<'static>,
+    /// Input that wasn't compressed yet
+    frame: Vec<u8>,
+    /// Compressed and decompressed size of each frame written
+    seek_table: Vec<(u32, u32)>,
+}
+
+impl<W: Write> SeekableZstdEncoder<W> {
+    /// Creates an encoder that compresses each frame with `compressor`
+    pub fn new(writer: W, compressor: Compressor<'static>) -> Self {
+        Self { writer: Some(writer), compressor, frame: Vec::with_capacity(FRAME_SIZE), seek_table: vec![] }
+    }
+
+    /// Writes the last frame and the seek table, and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().expect("writer is only taken when finishing"))
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        // Empty input still gets a frame, so the file starts like any other zstd file
+        if !self.frame.is_empty() || self.seek_table.is_empty() {
+            self.write_frame()?;
+        }
+
+        let mut table = vec![];
+        table.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        let content_size = self.seek_table.len() as u64 * 8 + FOOTER_SIZE;
+        table.extend_from_slice(&u32::try_from(content_size).map_err(|_| too_many_frames())?.to_le_bytes());
+        for (compressed_size, decompressed_size) in &self.seek_table {
+            table.extend_from_slice(&compressed_size.to_le_bytes());
+            table.extend_from_slice(&decompressed_size.to_le_bytes());
+        }
+        table.extend_from_slice(&(self.seek_table.len() as u32).to_le_bytes());
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+
+        let writer = self.writer.as_mut().expect("checked above");
+        writer.write_all(&table)?;
+        writer.flush()
+    }
+
+    /// Compresses the buffered input into a frame
+    fn write_frame(&mut self) -> io::Result<()> {
+        let compressed = self.compressor.compress(&self.frame)?;
+        // Frames are at most `FRAME_SIZE` bytes long, and compressed frames are barely larger
+        self.seek_table.push((compressed.len() as u32, self.frame.len() as u32));
+        self.frame.clear();
+
+        self.writer.as_mut().expect("writer is present while writing").write_all(&compressed)
+    }
+}
+
+impl<W: Write> Write for SeekableZstdEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(FRAME_SIZE - self.frame.len());
+        self.frame.extend_from_slice(&buf[..len]);
+        if self.frame.len() == FRAME_SIZE {
+            self.write_frame()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.frame.is_empty() {
+            self.write_frame()?;
+        }
+        self.writer.as_mut().expect("writer is present while writing").flush()
+    }
+}
+
+impl<W: Write> Drop for SeekableZstdEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+fn too_many_frames() -> io::Error {
+    io::Error::other("too many frames for a zstd seek table")
+}
+
+/// A frame listed in the seek table
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    compressed_offset: u64,
+    compressed_size: u32,
+    decompressed_offset: u64,
+    decompressed_size: u32,
+}
+
+impl Frame {
+    fn decompressed_end(&self) -> u64 {
+        self.decompressed_offset + u64::from(self.decompressed_size)
+    }
+}
+
+/// A decoder of seekable zstd files, which only decompresses the frames that are read
+///
+/// The checksums of the seek table aren't verified, the frames have their own checksums.
+pub struct SeekableZstdDecoder<R: Read + Seek> {
+    reader: R,
+    decompressor: Decompressor<'static>,
+    frames: Vec<Frame>,
+    /// Position in the decompressed data
+    position: u64,
+    /// Index of the frame decompressed into `buffer`
+    current_frame: Option<usize>,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read + Seek> SeekableZstdDecoder<R> {
+    /// Reads the seek table at the end of `reader`, `Ok(None)` means that the file isn't in the seekable format
+    pub fn new(mut reader: R, dictionary: Option<&[u8]>) -> io::Result<Option<Self>> {
+        let frames = match read_seek_table(&mut reader)? {
+            Some(frames) => frames,
+            None => return Ok(None),
+        };
+        let decompressor = match dictionary {
+            Some(dictionary) => Decompressor::with_dictionary(dictionary)?,
+            None => Decompressor::new()?,
+        };
+
+        Ok(Some(Self { reader, decompressor, frames, position: 0, current_frame: None, buffer: vec![] }))
+    }
+
+    /// Size of the decompressed data
+    fn len(&self) -> u64 {
+        self.frames.last().map_or(0, Frame::decompressed_end)
+    }
+
+    /// Decompresses the frame with the data at `self.position`, which must be before the end
+    fn load_frame(&mut self) -> io::Result<&Frame> {
+        let index = self.frames.partition_point(|frame| frame.decompressed_end() <= self.position);
+        let frame = &self.frames[index];
+
+        if self.current_frame != Some(index) {
+            self.current_frame = None;
+            let mut compressed = vec![0; frame.compressed_size as usize];
+            self.reader.seek(SeekFrom::Start(frame.compressed_offset))?;
+            self.reader.read_exact(&mut compressed)?;
+
+            self.buffer = self.decompressor.decompress(&compressed, frame.decompressed_size as usize)?;
+            if self.buffer.len() != frame.decompressed_size as usize {
+                return Err(corrupted());
+            }
+            self.current_frame = Some(index);
+        }
+
+        Ok(frame)
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len() {
+            return Ok(0);
+        }
+
+        let start = (self.position - self.load_frame()?.decompressed_offset) as usize;
+        let len = buf.len().min(self.buffer.len() - start);
+        buf[..len].copy_from_slice(&self.buffer[start..start + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableZstdDecoder<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match position {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+/// Reads the frames of the seek table at the end of `reader`, or `None` if there's no seek table
+fn read_seek_table(reader: &mut (impl Read + Seek)) -> io::Result<Option<Vec<Frame>>> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    if file_size < SKIPPABLE_HEADER_SIZE + FOOTER_SIZE {
+        return Ok(None);
+    }
+
+    let mut footer = [0; FOOTER_SIZE as usize];
+    reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    reader.read_exact(&mut footer)?;
+    if read_u32(&footer[5..]) != SEEKABLE_MAGIC {
+        return Ok(None);
+    }
+    let frame_count = u64::from(read_u32(&footer));
+    let descriptor = footer[4];
+    if descriptor & RESERVED_BITS != 0 {
+        return Err(corrupted());
+    }
+    let entry_size = if descriptor & CHECKSUM_FLAG != 0 { 12 } else { 8 };
+
+    let table_size = SKIPPABLE_HEADER_SIZE + frame_count * entry_size + FOOTER_SIZE;
+    if table_size > file_size {
+        return Err(corrupted());
+    }
+    let table_offset = file_size - table_size;
+    let mut table = vec![0; (table_size - FOOTER_SIZE) as usize];
+    reader.seek(SeekFrom::Start(table_offset))?;
+    reader.read_exact(&mut table)?;
+    if read_u32(&table) != SKIPPABLE_MAGIC || u64::from(read_u32(&table[4..])) != table_size - SKIPPABLE_HEADER_SIZE {
+        return Err(corrupted());
+    }
+
+    let mut frames = Vec::with_capacity(frame_count as usize);
+    let (mut compressed_offset, mut decompressed_offset) = (0, 0);
+    for entry in table[SKIPPABLE_HEADER_SIZE as usize..].chunks(entry_size as usize) {
+        let frame = Frame {
+            compressed_offset,
+            compressed_size: read_u32(entry),
+            decompressed_offset,
+            decompressed_size: read_u32(&entry[4..]),
+        };
+        if frame.decompressed_size > MAX_FRAME_DECOMPRESSED_SIZE {
+            return Err(corrupted());
+        }
+        compressed_offset += u64::from(frame.compressed_size);
+        decompressed_offset += u64::from(frame.decompressed_size);
+        frames.push(frame);
+    }
+    // The frames fill the file up to the seek table
+    if compressed_offset != table_offset {
+        return Err(corrupted());
+    }
+
+    Ok(Some(frames))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().expect("slice has 4 bytes"))
+}
+
+fn corrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupted zstd seek table")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = SeekableZstdEncoder::new(vec![], Compressor::new(3).unwrap());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_seekable_zstd_round_trip() {
+        let text: Vec<u8> = (0..600_000u32).flat_map(|i| format!("line {}\n", i % 7919).into_bytes()).collect();
+
+        for len in [0, 1, FRAME_SIZE, FRAME_SIZE + 1, text.len()] {
+            let data = &text[..len];
+            let compressed = compress(data);
+            // Any zstd decoder can decompress it, skipping the seek table
+            assert_eq!(zstd::stream::decode_all(&compressed[..]).unwrap(), data);
+
+            let mut decoder = SeekableZstdDecoder::new(Cursor::new(compressed), None).unwrap().unwrap();
+            let mut decompressed = vec![];
+            decoder.read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn test_seekable_zstd_random_access() {
+        let text: Vec<u8> = (0..600_000u32).flat_map(|i| format!("line {}\n", i % 7919).into_bytes()).collect();
+        let mut decoder = SeekableZstdDecoder::new(Cursor::new(compress(&text)), None).unwrap().unwrap();
+
+        // Across the boundary of the first two frames, then backwards
+        for position in [FRAME_SIZE - 10, 5, text.len() - 3] {
+            let mut buf = [0; 20];
+            decoder.seek(SeekFrom::Start(position as u64)).unwrap();
+            let len = decoder.read(&mut buf).unwrap();
+            let mut rest = vec![];
+            decoder.read_to_end(&mut rest).unwrap();
+            assert_eq!([&buf[..len], &rest].concat(), &text[position..]);
+        }
+
+        decoder.seek(SeekFrom::End(-4)).unwrap();
+        let mut end = vec![];
+        decoder.read_to_end(&mut end).unwrap();
+        assert_eq!(end, &text[text.len() - 4..]);
+    }
+
+    #[test]
+    fn test_not_seekable() {
+        let compressed = zstd::stream::encode_all(&b"not seekable"[..], 3).unwrap();
+        assert!(SeekableZstdDecoder::new(Cursor::new(compressed), None).unwrap().is_none());
+    }
+}
@@ -1,8 +1,13 @@
 #[macro_use]
 mod utils;
 
-use std::{iter::once, path::PathBuf};
+use std::{
+    iter::once,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
 
+use assert_cmd::Command;
 use fs_err as fs;
 use parse_display::Display;
 use proptest::sample::size_range;
@@ -12,36 +17,57 @@ use test_strategy::{proptest, Arbitrary};
 
 use crate::utils::{assert_same_directory, write_random_content};
 
-// tar and zip extensions
+// archive extensions
 #[derive(Arbitrary, Debug, Display)]
 #[display(style = "lowercase")]
 enum DirectoryExtension {
+    Cpio,
     Tar,
+    Taz,
+    Tbr,
     Tbz,
     Tbz2,
     Tgz,
+    Tlz,
     Tlz4,
     Tlzma,
     Tsz,
     Txz,
     Tzst,
     Zip,
+    #[display("7z")]
+    SevenZ,
 }
 
 // extensions of single file compression formats
 #[derive(Arbitrary, Debug, Display)]
 #[display(style = "lowercase")]
 enum FileExtension {
+    Br,
     Bz,
     Bz2,
     Gz,
+    Lz,
     Lz4,
     Lzma,
     Sz,
     Xz,
+    #[display("Z")]
+    Z,
     Zst,
 }
 
+// compression methods of the files inside of zip archives
+#[derive(Arbitrary, Debug, Display)]
+#[display(style = "lowercase")]
+enum ZipMethod {
+    Auto,
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
+}
+
 #[derive(Arbitrary, Debug, Display)]
 #[display("{0}")]
 enum Extension {
@@ -69,7 +95,7 @@ fn create_random_files(dir: impl Into<PathBuf>, depth: u8, rng: &mut SmallRng) {
 
     // create more random files in 0 to 3 new directories
     for _ in 0..rng.gen_range(0..4u32) {
-        create_random_files(&tempfile::tempdir_in(dir).unwrap().into_path(), depth - 1, rng);
+        create_random_files(tempfile::tempdir_in(dir).unwrap().into_path(), depth - 1, rng);
     }
 }
 
@@ -124,3 +150,721 @@ fn multiple_files(
     ouch!("-A", "d", archive, "-d", after);
     assert_same_directory(before, after, !matches!(ext, DirectoryExtension::Zip));
 }
+
+// compress and decompress a directory into a file without extensions, using the --format flag
+#[proptest(cases = 128)]
+fn multiple_files_with_format(
+    ext: DirectoryExtension,
+    #[any(size_range(0..4).lift())] exts: Vec<FileExtension>,
+    #[strategy(0u8..4)] depth: u8,
+) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join("archive.bin");
+    let format = merge_extensions(&ext, exts);
+    let after = &dir.join("after");
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    ouch!("-A", "c", before_dir, archive, "--format", &format);
+    ouch!("-A", "d", archive, "--format", &format, "-d", after);
+    assert_same_directory(before, after, !matches!(ext, DirectoryExtension::Zip));
+}
+
+// compress and decompress a directory with the fastest or the best compression level of each format
+#[proptest(cases = 64)]
+fn multiple_files_with_level(
+    ext: DirectoryExtension,
+    #[any(size_range(0..4).lift())] exts: Vec<FileExtension>,
+    #[strategy(0u8..4)] depth: u8,
+    best: bool,
+) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join(format!("archive.{}", merge_extensions(&ext, exts)));
+    let after = &dir.join("after");
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    ouch!("-A", "c", before_dir, archive, if best { "--best" } else { "--fast" });
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, !matches!(ext, DirectoryExtension::Zip));
+}
+
+// compress and decompress a directory using several threads in the formats that support them
+#[proptest(cases = 64)]
+fn multiple_files_with_threads(
+    ext: DirectoryExtension,
+    #[any(size_range(0..4).lift())] exts: Vec<FileExtension>,
+    #[strategy(0u8..4)] depth: u8,
+    #[strategy(1u8..5)] threads: u8,
+) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join(format!("archive.{}", merge_extensions(&ext, exts)));
+    let after = &dir.join("after");
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    ouch!("-A", "c", before_dir, archive, "--threads", threads.to_string());
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, !matches!(ext, DirectoryExtension::Zip));
+}
+
+// compress and decompress a directory into a zip archive with each compression method
+#[proptest(cases = 32)]
+fn multiple_files_with_zip_method(
+    method: ZipMethod,
+    #[any(size_range(0..4).lift())] exts: Vec<FileExtension>,
+    #[strategy(0u8..4)] depth: u8,
+) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join(format!("archive.{}", merge_extensions("zip", exts)));
+    let after = &dir.join("after");
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    ouch!("-A", "c", before_dir, archive, "--zip-method", method.to_string());
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, false);
+}
+
+// chained zip archives are decoded into a temporary file, without asking to load them into memory
+#[test]
+fn chained_zip_archive() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    create_random_files(before_dir, 2, &mut SmallRng::from_entropy());
+    fs::write(before_dir.join("notes.txt"), "notes").unwrap();
+    let archive = &dir.join("archive.zip.xz");
+    let after = &dir.join("after");
+
+    ouch!("-A", "c", before_dir, archive);
+
+    // A question would be answered with no, which would leave the archive undecompressed
+    let run = |args: &[&std::ffi::OsStr]| {
+        Command::cargo_bin("ouch").unwrap().arg("-A").args(args).write_stdin("n\n").assert()
+    };
+    let output = run(&["d".as_ref(), archive.as_os_str(), "-d".as_ref(), after.as_os_str()]).success();
+    assert!(!String::from_utf8_lossy(&output.get_output().stdout).contains("memory"));
+    assert_same_directory(before, after, false);
+
+    run(&["l".as_ref(), archive.as_os_str()]).success();
+    run(&["cat".as_ref(), archive.as_os_str(), "dir/notes.txt".as_ref()]).success().stdout("notes");
+}
+
+// with the auto method, only the files of zip archives that are already compressed are stored
+#[test]
+fn zip_method_auto() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let text = &dir.join("text");
+    fs::write(text, "lorem ipsum ".repeat(100_000)).unwrap();
+    let input = &dir.join("input");
+    fs::create_dir_all(input).unwrap();
+    // Tar archives aren't compressed, unlike the formats chained to them
+    ouch!("-A", "c", text, input.join("copy.tar"));
+    ouch!("-A", "c", text, input.join("copy.tar.gz"));
+    fs::copy(text, input.join("plain.txt")).unwrap();
+    // Detected from their magic bytes
+    for extension in ["tar", "cpio", "ar"] {
+        let archive = &dir.join(format!("copy.{}", extension));
+        ouch!("-A", "c", text, archive);
+        fs::copy(archive, input.join(format!("{}-data", extension))).unwrap();
+    }
+
+    let archive = &dir.join("archive.zip");
+    ouch!("-A", "c", input, archive);
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(archive).unwrap()).unwrap();
+    let mut compression = |name: &str| archive.by_name(&format!("input/{}", name)).unwrap().compression();
+    assert_eq!(compression("copy.tar"), zip::CompressionMethod::Deflated);
+    assert_eq!(compression("plain.txt"), zip::CompressionMethod::Deflated);
+    assert_eq!(compression("tar-data"), zip::CompressionMethod::Deflated);
+    assert_eq!(compression("cpio-data"), zip::CompressionMethod::Deflated);
+    assert_eq!(compression("ar-data"), zip::CompressionMethod::Deflated);
+    assert_eq!(compression("copy.tar.gz"), zip::CompressionMethod::Stored);
+}
+
+// compress data from stdin into stdout, then decompress it back the same way
+#[proptest(cases = 128)]
+fn single_file_stdio(ext: FileExtension, #[any(size_range(0..4).lift())] exts: Vec<FileExtension>) {
+    let format = merge_extensions(ext, exts);
+    let mut data = Vec::new();
+    write_random_content(&mut data, &mut SmallRng::from_entropy());
+
+    let compressed = Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "c", "-", "-", "--format", &format])
+        .write_stdin(data.clone())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "d", "-", "--format", &format])
+        .write_stdin(compressed)
+        .assert()
+        .success()
+        .stdout(data);
+}
+
+// decompress and list archives read from stdin, detecting all of their formats through their magic bytes
+#[test]
+fn archive_from_stdin() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    create_random_files(before_dir, 2, &mut SmallRng::from_entropy());
+    fs::write(before_dir.join("notes.txt"), "notes").unwrap();
+
+    for format in ["zip", "tar.gz", "tar.xz.gz", "zip.bz2"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        let after = &dir.join(format!("after-{format}"));
+        ouch!("-A", "c", before_dir, archive);
+
+        Command::cargo_bin("ouch")
+            .unwrap()
+            .args(["-A", "d", "-", "-d"])
+            .arg(after)
+            .write_stdin(fs::read(archive).unwrap())
+            .assert()
+            .success();
+        assert_same_directory(before, after, false);
+
+        let output = Command::cargo_bin("ouch")
+            .unwrap()
+            .args(["-A", "l", "-"])
+            .write_stdin(fs::read(archive).unwrap())
+            .assert()
+            .success();
+        let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+        assert!(stdout.lines().any(|line| line == "dir/notes.txt"));
+
+        Command::cargo_bin("ouch")
+            .unwrap()
+            .args(["-A", "cat", "-", "dir/notes.txt"])
+            .write_stdin(fs::read(archive).unwrap())
+            .assert()
+            .success()
+            .stdout("notes");
+    }
+}
+
+// compress, decompress and list a directory into an encrypted zip archive
+#[test]
+fn encrypted_zip() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join("archive.zip");
+    let after = &dir.join("after");
+    create_random_files(before_dir, 2, &mut SmallRng::from_entropy());
+    fs::write(before_dir.join("notes.txt"), "notes").unwrap();
+    ouch!("-A", "c", before_dir, archive, "--password", "correct horse");
+
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "d", "--password", "battery staple", "-d"])
+        .arg(dir.join("wrong"))
+        .arg(archive)
+        .assert()
+        .failure();
+
+    ouch!("-A", "d", archive, "-d", after, "--password", "correct horse");
+    assert_same_directory(before, after, false);
+
+    let output = Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "l", "--password", "correct horse"])
+        .arg(archive)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    assert!(stdout.lines().any(|line| line == "dir/notes.txt"));
+
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "l", "--password", "battery staple"])
+        .arg(archive)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn rar_archives_are_read_only() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let file = &dir.join("file");
+    fs::write(file, "contents").unwrap();
+
+    Command::cargo_bin("ouch").unwrap().args(["-A", "c"]).arg(file).arg(dir.join("archive.rar")).assert().failure();
+    assert!(!dir.join("archive.rar").exists());
+}
+
+// older versions wrote xz files with the .lzma extension, they're detected and decompressed as xz
+#[test]
+fn xz_with_lzma_extension() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let archive = &dir.join("archive.tar.xz");
+    let after = &dir.join("after");
+    create_random_files(before_dir, 2, &mut SmallRng::from_entropy());
+    ouch!("-A", "c", before_dir, archive);
+
+    let renamed = &dir.join("archive.tar.lzma");
+    fs::rename(archive, renamed).unwrap();
+    ouch!("-A", "d", renamed, "-d", after);
+    assert_same_directory(before, after, false);
+}
+
+// ar archives, like .deb packages, only hold files
+#[test]
+fn ar_archives_only_hold_files() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let mut rng = SmallRng::from_entropy();
+    for name in ["debian-binary", "a file name longer than sixteen bytes"] {
+        write_random_content(&mut fs::File::create(before.join(name)).unwrap(), &mut rng);
+    }
+    let archive = &dir.join("package.deb");
+    let after = &dir.join("after");
+    ouch!("-A", "c", before.join("debian-binary"), before.join("a file name longer than sixteen bytes"), archive);
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after.join("package"), true);
+
+    Command::cargo_bin("ouch").unwrap().args(["-A", "c"]).arg(before).arg(dir.join("archive.ar")).assert().failure();
+    assert!(!dir.join("archive.ar").exists());
+}
+
+// compress and decompress small files with a trained zstd dictionary
+#[test]
+fn zstd_dictionary() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let samples = &dir.join("samples");
+    fs::create_dir(samples).unwrap();
+    let mut rng = SmallRng::from_entropy();
+    for i in 0..500 {
+        let record = format!(
+            r#"{{"id": {}, "name": "user{}", "email": "user{}@example.com", "active": {}, "roles": ["viewer"]}}"#,
+            i,
+            rng.gen_range(0..1000),
+            i,
+            rng.gen::<bool>()
+        );
+        fs::write(samples.join(format!("{}.json", i)), record).unwrap();
+    }
+    let dict = &dir.join("dict");
+    ouch!("-A", "zstd-train", samples, "-o", dict);
+
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    fs::copy(samples.join("42.json"), before.join("42.json")).unwrap();
+    let archive = &dir.join("42.json.zst");
+    let after = &dir.join("after");
+    ouch!("-A", "c", before.join("42.json"), archive, "--zstd-dict", dict);
+    ouch!("-A", "d", archive, "-d", after, "--zstd-dict", dict);
+    assert_same_directory(before, after, false);
+
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "d", "-d"])
+        .arg(dir.join("without_dict"))
+        .arg(archive)
+        .assert()
+        .failure();
+}
+
+// compress with zstd's long distance matching, the large window is accepted when decompressing
+#[test]
+fn zstd_long_distance_matching() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    fs::create_dir(before).unwrap();
+    let mut data = vec![0; 100_000];
+    SmallRng::from_entropy().fill(&mut data[..]);
+    fs::write(before.join("file"), data.repeat(3)).unwrap();
+
+    // The window of 1 GiB is larger than what zstd decompresses by default
+    let archive = &dir.join("archive.tar.zst");
+    let after = &dir.join("after");
+    ouch!("-A", "c", before, archive, "--zstd-long=30");
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after.join("before"), false);
+
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "c", "--zstd-long=32"])
+        .arg(before)
+        .arg(dir.join("invalid.tar.zst"))
+        .assert()
+        .failure();
+}
+
+// tarballs in the seekable format of zstd are listed and decompressed with random access
+#[test]
+fn zstd_seekable_tarball() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let mut rng = SmallRng::from_entropy();
+    create_random_files(before_dir, 2, &mut rng);
+    // Larger than a frame, so it spans several of them
+    let mut data = vec![0; 5_000_000];
+    rng.fill(&mut data[..]);
+    fs::write(before_dir.join("large"), data).unwrap();
+    fs::write(before_dir.join("small"), "after the large file").unwrap();
+
+    let archive = &dir.join("archive.tar.zst");
+    let after = &dir.join("after");
+    ouch!("-A", "c", before_dir, archive, "--zstd-seekable");
+    // The seek table ends with the magic number of the seekable format
+    assert!(fs::read(archive).unwrap().ends_with(&[0xB1, 0xEA, 0x92, 0x8F]));
+
+    let listing = Command::cargo_bin("ouch").unwrap().args(["-A", "l"]).arg(archive).assert().success();
+    let listing = String::from_utf8(listing.get_output().stdout.clone()).unwrap();
+    assert!(listing.contains("dir/large") && listing.contains("dir/small"));
+
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, false);
+}
+
+// only the members of archives selected by paths and globs are decompressed
+#[test]
+fn selected_members() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("input");
+    for path in
+        ["src/main.rs", "src/archive/tar.rs", "src/archive/notes.txt", "src/generated/opts.rs", "docs/README.md"]
+    {
+        let path = input.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "contents").unwrap();
+    }
+
+    let expected = &dir.join("expected");
+    for path in ["src/main.rs", "src/archive/tar.rs", "docs/README.md"] {
+        let path = expected.join("input").join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "contents").unwrap();
+    }
+
+    for extension in ["tar.gz", "zip", "7z", "cpio"] {
+        let archive = &dir.join(format!("archive.{}", extension));
+        let after = &dir.join(format!("after_{}", extension));
+        ouch!("-A", "c", input, archive);
+        ouch!(
+            "-A",
+            "d",
+            archive,
+            "-d",
+            after,
+            "--exclude",
+            "input/src/generated",
+            "--",
+            "input/src/**/*.rs",
+            "input/docs"
+        );
+        assert_same_directory(expected, after, false);
+
+        // Nothing is decompressed if a member isn't found
+        let missing = &dir.join(format!("missing_{}", extension));
+        Command::cargo_bin("ouch")
+            .unwrap()
+            .args(["-A", "d", "-d"])
+            .arg(missing)
+            .arg(archive)
+            .args(["--", "input/docs", "input/missing"])
+            .assert()
+            .failure();
+        assert!(fs::read_dir(missing).unwrap().next().is_none());
+    }
+}
+
+// the paths of the members of archives are rewritten before they're decompressed
+#[test]
+fn rewritten_paths() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("project-1.2.3");
+    for path in ["src/main.rs", "docs/README.txt"] {
+        let path = input.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "contents").unwrap();
+    }
+
+    let stripped = &dir.join("stripped");
+    fs::create_dir_all(stripped.join("src")).unwrap();
+    // The directory is left empty, its file was moved by the transform
+    fs::create_dir_all(stripped.join("docs")).unwrap();
+    fs::write(stripped.join("src/main.rs"), "contents").unwrap();
+    fs::write(stripped.join("README.md"), "contents").unwrap();
+
+    let transformed = &dir.join("transformed");
+    fs::create_dir_all(transformed.join("project/src")).unwrap();
+    fs::create_dir_all(transformed.join("project/docs")).unwrap();
+    fs::write(transformed.join("project/src/main.rs"), "contents").unwrap();
+    fs::write(transformed.join("project/docs/README.txt"), "contents").unwrap();
+
+    for extension in ["tar.gz", "zip"] {
+        let archive = &dir.join(format!("archive.{}", extension));
+        ouch!("-A", "c", input, archive);
+
+        let after = &dir.join(format!("stripped_{}", extension));
+        ouch!("-A", "d", archive, "-d", after, "--strip-components", "1", "--transform", r"s|^docs/(.*)\.txt$|\1.md|");
+        assert_same_directory(stripped, after, false);
+
+        let after = &dir.join(format!("transformed_{}", extension));
+        ouch!("-A", "d", archive, "-d", after, "--transform", r"s/^project-[^\/]*/project/");
+        assert_same_directory(transformed, after, false);
+    }
+}
+
+// a single member of an archive, or a whole compressed file, is written to stdout
+#[test]
+fn cat_member() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("input");
+    fs::create_dir_all(input.join("src")).unwrap();
+    fs::write(input.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(input.join("notes.txt"), "notes").unwrap();
+
+    let cat = |args: &[&std::ffi::OsStr]| Command::cargo_bin("ouch").unwrap().arg("cat").args(args).assert();
+
+    for extension in ["tar.gz", "tar.zst", "zip", "7z", "cpio.xz"] {
+        let archive = &dir.join(format!("archive.{}", extension));
+        ouch!("-A", "c", input, archive);
+
+        cat(&[archive.as_os_str(), "input/src/main.rs".as_ref()]).success().stdout("fn main() {}");
+        cat(&[archive.as_os_str(), "./input/notes.txt".as_ref()]).success().stdout("notes");
+        // Directories and missing members can't be written
+        cat(&[archive.as_os_str(), "input/src".as_ref()]).failure().stdout("");
+        cat(&[archive.as_os_str(), "input/missing".as_ref()]).failure().stdout("");
+        // The member must be given for archives
+        cat(&[archive.as_os_str()]).failure();
+    }
+
+    let compressed = &dir.join("notes.txt.gz.zst");
+    ouch!("-A", "c", input.join("notes.txt"), compressed);
+    cat(&[compressed.as_os_str()]).success().stdout("notes");
+    cat(&[compressed.as_os_str(), "notes.txt".as_ref()]).failure();
+}
+
+// files are converted between formats, tar and zip archives into each other keeping the modes and mtimes of their files
+#[test]
+fn convert_formats() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let input = &before.join("input");
+    fs::create_dir_all(input.join("src")).unwrap();
+    fs::write(input.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(input.join("text"), "lorem ipsum ".repeat(10_000)).unwrap();
+    let script = std::fs::File::create(input.join("script")).unwrap();
+    script.set_modified(UNIX_EPOCH + Duration::from_secs(1_600_000_000)).unwrap();
+    #[cfg(unix)]
+    {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+        script.set_permissions(Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let archive = &dir.join("archive.tar.gz");
+    ouch!("-A", "c", input, archive);
+
+    for (from, to) in [("tar.gz", "tar.zst"), ("tar.gz", "zip"), ("zip", "tar.xz"), ("tar.xz", "tar")] {
+        let from = &dir.join(format!("archive.{}", from));
+        let to = &dir.join(format!("archive.{}", to));
+        ouch!("-A", "convert", from, to);
+
+        let after = &dir.join(format!("after-{}", to.file_name().unwrap().to_str().unwrap()));
+        ouch!("-A", "d", to, "-d", after);
+        assert_same_directory(before, after, true);
+
+        let script = fs::metadata(after.join("input/script")).unwrap();
+        assert_eq!(script.modified().unwrap(), UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(script.permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    let compressed = &dir.join("text.gz");
+    let converted = &dir.join("text.bz2.zst");
+    ouch!("-A", "c", input.join("text"), compressed);
+    ouch!("-A", "convert", compressed, converted);
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .arg("cat")
+        .arg(converted)
+        .assert()
+        .success()
+        .stdout("lorem ipsum ".repeat(10_000));
+
+    // Archives can't be converted into single file formats
+    Command::cargo_bin("ouch")
+        .unwrap()
+        .args(["-A", "convert"])
+        .arg(archive)
+        .arg(dir.join("text.xz"))
+        .assert()
+        .failure();
+    assert!(!dir.join("text.xz").exists());
+}
+
+// RAR4 and RAR5 archives, the encrypted ones with their password, are listed, decompressed, written to stdout and tested
+#[test]
+fn rar_archives() {
+    let data = &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let gitignore = "target\nCargo.lock\n";
+
+    for (archive, password, member, contents) in [
+        ("rar4.rar", None, "VERSION", "unrar-0.4.0"),
+        ("rar4-encrypted.rar", Some("unrar"), ".gitignore", gitignore),
+        ("rar5.rar", None, ".gitignore", gitignore),
+        ("rar5-encrypted.rar", Some("password"), ".gitignore", gitignore),
+    ] {
+        let archive = &data.join(archive);
+        // Runs the command on the archive, with its password if it has one
+        let run = |command: &str| {
+            let mut run = Command::cargo_bin("ouch").unwrap();
+            run.args(["-A", command])
+                .arg(archive)
+                .args(password.map(|password| ["--password", password]).iter().flatten());
+            run
+        };
+
+        // The names of the files are encrypted too in rar5-encrypted.rar
+        let output = run("l").assert().success();
+        let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+        assert_eq!(stdout.lines().skip(1).collect::<Vec<_>>(), [member]);
+
+        let output = &dir.join(archive.file_stem().unwrap());
+        run("d").arg("-d").arg(output).assert().success();
+        assert_eq!(fs::read_to_string(output.join(member)).unwrap(), contents);
+
+        run("cat").arg(member).assert().success().stdout(contents);
+        run("test").assert().success();
+
+        if password.is_some() {
+            let output = &dir.join("wrong-password");
+            Command::cargo_bin("ouch")
+                .unwrap()
+                .args(["-A", "d"])
+                .arg(archive)
+                .args(["--password", "wrong", "-d"])
+                .arg(output)
+                .assert()
+                .failure();
+        }
+    }
+}
+
+// 7z archives of only directories and empty files, which have no contents to compress, are listed and decompressed
+#[test]
+fn sevenz_without_contents() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let input = &before.join("input");
+    for path in ["A/B", "C/D/E"] {
+        fs::create_dir_all(input.join(path)).unwrap();
+    }
+    for path in ["A/x", "A/y", "A/z"] {
+        fs::write(input.join(path), "").unwrap();
+    }
+    let archive = &dir.join("archive.7z");
+    let after = &dir.join("after");
+
+    ouch!("-A", "c", input, archive);
+
+    let output = Command::cargo_bin("ouch").unwrap().args(["-A", "l"]).arg(archive).assert().success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let mut listed: Vec<_> = stdout.lines().skip(1).collect();
+    listed.sort_unstable();
+    assert_eq!(
+        listed,
+        [
+            "input/",
+            "input/A/",
+            "input/A/B/",
+            "input/A/x",
+            "input/A/y",
+            "input/A/z",
+            "input/C/",
+            "input/C/D/",
+            "input/C/D/E/"
+        ]
+    );
+
+    ouch!("-A", "d", archive, "-d", after);
+    assert_same_directory(before, after, true);
+}
+
+// archives and compressed files are tested without writing anything, corrupted ones fail
+#[test]
+fn integrity_test() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let input = &dir.join("input");
+    fs::create_dir_all(input).unwrap();
+    let mut rng = SmallRng::from_entropy();
+    let mut data = vec![0; 100_000];
+    rng.fill(&mut data[..]);
+    fs::write(input.join("random"), data).unwrap();
+    fs::write(input.join("text"), "lorem ipsum ".repeat(10_000)).unwrap();
+
+    for extension in ["tar.gz", "tar.xz", "tar.zst", "tar.lz4", "zip", "7z", "cpio.bz2", "txt.gz"] {
+        let archive = &dir.join(format!("archive.{}", extension));
+        let source = if extension == "txt.gz" { input.join("text") } else { input.clone() };
+        ouch!("-A", "c", source, archive);
+
+        let corrupted = &dir.join(format!("corrupted.{}", extension));
+        let mut bytes = fs::read(archive).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        fs::write(corrupted, bytes).unwrap();
+
+        Command::cargo_bin("ouch")
+            .unwrap()
+            .arg("test")
+            .arg(archive)
+            .assert()
+            .success()
+            .stdout(format!("OK    {}\n", archive.display()));
+
+        // The other files are still tested after a failure
+        let output = Command::cargo_bin("ouch").unwrap().arg("test").arg(corrupted).arg(archive).assert().failure();
+        let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+        assert!(stdout.starts_with(&format!("FAIL  {}", corrupted.display())), "{}", stdout);
+        assert!(stdout.ends_with(&format!("OK    {}\n", archive.display())), "{}", stdout);
+    }
+}
@@ -15,8 +15,7 @@ macro_rules! ouch {
 
 // write random content to a file
 pub fn write_random_content(file: &mut impl Write, rng: &mut impl RngCore) {
-    let mut data = Vec::new();
-    data.resize(rng.gen_range(0..8192), 0);
+    let mut data = vec![0; rng.gen_range(0..8192)];
     rng.fill_bytes(&mut data);
     file.write_all(&data).unwrap();
 }